@@ -1,11 +1,20 @@
 use std::{cell::RefCell, collections::HashMap, rc::Rc};
 
-use crate::MalVal;
+use crate::{Arity, MalError, MalVal, Port};
+
+thread_local! {
+    static DYNAMIC: RefCell<HashMap<String, Rc<MalVal>>> = RefCell::new(HashMap::from([
+        ("*in*".to_string(), Rc::new(MalVal::Port(Rc::new(Port::stdin())))),
+        ("*out*".to_string(), Rc::new(MalVal::Port(Rc::new(Port::stdout())))),
+        ("*err*".to_string(), Rc::new(MalVal::Port(Rc::new(Port::stderr())))),
+    ]));
+}
 
 #[derive(Default)]
 pub struct Env {
     outer: Option<Rc<RefCell<Env>>>,
     data: HashMap<String, Rc<MalVal>>,
+    fn_name: Option<String>,
 }
 
 impl Env {
@@ -13,25 +22,51 @@ impl Env {
         Self {
             outer: Some(outer),
             data: Default::default(),
+            fn_name: None,
+        }
+    }
+
+    pub fn for_call(outer: Rc<RefCell<Env>>, fn_name: String) -> Self {
+        Self {
+            fn_name: Some(fn_name),
+            ..Self::new(outer)
         }
     }
 
-    pub fn bind_expr(&mut self, binds: Vec<String>, exprs: Vec<Rc<MalVal>>) {
+    pub fn fn_name(&self) -> Option<String> {
+        self.fn_name
+            .clone()
+            .or_else(|| self.outer.as_ref()?.borrow().fn_name())
+    }
+
+    pub fn bind_expr(
+        &mut self,
+        name: &str,
+        binds: Vec<String>,
+        exprs: Vec<Rc<MalVal>>,
+    ) -> Result<(), MalError> {
+        let n = exprs.len();
         let mut bind_iter = binds.into_iter();
-        let mut expr_iter = exprs.into_iter().peekable();
+        let mut expr_iter = exprs.into_iter();
         while let Some(k) = bind_iter.next() {
             match k.as_str() {
                 "&" => {
-                    let k = bind_iter.next().unwrap();
-                    let v = Rc::new(MalVal::List(expr_iter.collect::<Vec<_>>(), None));
-                    self.data.insert(k, v);
-                    break;
+                    if let Some(k) = bind_iter.next() {
+                        let v = Rc::new(MalVal::List(expr_iter.collect(), None));
+                        self.data.insert(k, v);
+                    }
+                    return Ok(());
                 }
                 _ => {
-                    self.data.insert(k, expr_iter.next().unwrap());
+                    let v = expr_iter.next().ok_or_else(|| Arity::error(name, n))?;
+                    self.data.insert(k, v);
                 }
             }
         }
+        match expr_iter.next() {
+            Some(_) => Err(Arity::error(name, n)),
+            None => Ok(()),
+        }
     }
 
     pub fn set(&mut self, symbol: String, value: Rc<MalVal>) {
@@ -39,10 +74,24 @@ impl Env {
     }
 
     pub fn get(&self, symbol: &str) -> Option<Rc<MalVal>> {
-        self.data.get(symbol).cloned().or_else(|| {
-            self.outer
-                .as_ref()
-                .and_then(|o| o.as_ref().borrow().get(symbol))
+        self.data
+            .get(symbol)
+            .cloned()
+            .or_else(|| match &self.outer {
+                Some(o) => o.as_ref().borrow().get(symbol),
+                None => Self::dynamic(symbol),
+            })
+    }
+
+    pub fn dynamic(symbol: &str) -> Option<Rc<MalVal>> {
+        DYNAMIC.with(|vars| vars.borrow().get(symbol).cloned())
+    }
+
+    pub fn rebind(symbol: &str, value: Rc<MalVal>) -> Option<Rc<MalVal>> {
+        DYNAMIC.with(|vars| {
+            vars.borrow_mut()
+                .get_mut(symbol)
+                .map(|v| std::mem::replace(v, value))
         })
     }
 }
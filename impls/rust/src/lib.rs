@@ -5,5 +5,8 @@ mod types;
 
 pub use env::Env;
 pub use malcore::NS;
-pub use reader::read_str;
-pub use types::{MalError, MalFn, MalResult, MalVal};
+pub use reader::{read_file, read_str};
+pub use types::{
+    Arity, Hashable, LazySeq, MalError, MalFn, MalRegex, MalResult, MalVal, Port, ReadError,
+    SeqIter, SortedMap, Trace,
+};
@@ -1,109 +1,423 @@
-use std::{cell::Cell, collections::HashMap, io::Write, rc::Rc};
-
-use crate::{read_str, MalError, MalResult, MalVal};
-
-pub const NS: [(&str, fn(&[Rc<MalVal>]) -> MalResult); 61] = [
-    ("+", add),
-    ("-", sub),
-    ("*", mul),
-    ("/", div),
-    ("prn", prn),
-    ("list", list),
-    ("list?", is_list),
-    ("empty?", is_empty),
-    ("count", count),
-    ("=", eq),
-    ("<", lt),
-    ("<=", lte),
-    (">", gt),
-    (">=", gte),
-    ("pr-str", pr_str),
-    ("str", str),
-    ("println", println),
-    ("read-string", read_string),
-    ("slurp", slurp),
-    ("atom", atom),
-    ("atom?", is_atom),
-    ("deref", deref),
-    ("reset!", reset),
-    ("swap!", swap),
-    ("cons", cons),
-    ("concat", concat),
-    ("vec", vec),
-    ("nth", nth),
-    ("first", first),
-    ("rest", rest),
-    ("throw", throw),
-    ("apply", apply),
-    ("map", map),
-    ("nil?", is_nil),
-    ("true?", is_true),
-    ("false?", is_false),
-    ("symbol?", is_symbol),
-    ("symbol", symbol),
-    ("keyword", keyword),
-    ("keyword?", is_keyword),
-    ("vector", vector),
-    ("vector?", is_vector),
-    ("sequential?", is_sequential),
-    ("hash-map", hash_map),
-    ("map?", is_map),
-    ("assoc", assoc),
-    ("dissoc", dissoc),
-    ("get", get),
-    ("contains?", is_contains),
-    ("keys", keys),
-    ("vals", vals),
-    ("readline", readline),
-    ("time-ms", time_ms),
-    ("meta", meta),
-    ("with-meta", with_meta),
-    ("fn?", is_fn),
-    ("string?", is_string),
-    ("number?", is_number),
-    ("seq", seq),
-    ("conj", conj),
-    ("macro?", is_macro),
+use std::{
+    cell::Cell,
+    cmp::Ordering,
+    fs,
+    io::{BufReader, BufWriter, Cursor, Write},
+    path::Path,
+    rc::Rc,
+    time::UNIX_EPOCH,
+};
+
+use im_rc::{vector, HashMap, HashSet, Vector};
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{Pow, ToPrimitive, Zero};
+use regex::Captures;
+
+use crate::{
+    read_str,
+    types::{compare_with, compile_regex, sorted_entries, Hashable},
+    Arity, Env, LazySeq, MalError, MalFn, MalRegex, MalResult, MalVal, Port, SeqIter, SortedMap,
+};
+
+type NsFn = fn(&[Rc<MalVal>]) -> MalResult;
+
+pub const NS: [(&str, Arity, NsFn); 152] = [
+    ("+", Arity::at_least(0), add),
+    ("-", Arity::at_least(1), sub),
+    ("*", Arity::at_least(0), mul),
+    ("/", Arity::at_least(1), div),
+    ("prn", Arity::at_least(0), prn),
+    ("list", Arity::at_least(0), list),
+    ("list?", Arity::exact(1), is_list),
+    ("empty?", Arity::exact(1), is_empty),
+    ("count", Arity::exact(1), count),
+    ("=", Arity::exact(2), eq),
+    ("<", Arity::at_least(1), lt),
+    ("<=", Arity::at_least(1), lte),
+    (">", Arity::at_least(1), gt),
+    (">=", Arity::at_least(1), gte),
+    ("pr-str", Arity::at_least(0), pr_str),
+    ("str", Arity::at_least(0), str),
+    ("println", Arity::at_least(0), println),
+    ("read-string", Arity::exact(1), read_string),
+    ("slurp", Arity::exact(1), slurp),
+    ("spit", Arity::at_least(2), spit),
+    ("file-exists?", Arity::exact(1), is_file_exists),
+    ("delete-file", Arity::between(1, 2), delete_file),
+    ("list-dir", Arity::exact(1), list_dir),
+    ("mkdir", Arity::exact(1), mkdir),
+    ("file-info", Arity::exact(1), file_info),
+    ("line-seq", Arity::exact(1), line_seq),
+    ("open-reader", Arity::exact(1), open_reader),
+    ("open-writer", Arity::at_least(1), open_writer),
+    ("string-reader", Arity::exact(1), string_reader),
+    ("string-writer", Arity::exact(0), string_writer),
+    ("port-contents", Arity::exact(1), port_contents),
+    ("close", Arity::exact(1), close),
+    ("port?", Arity::exact(1), is_port),
+    ("read-line", Arity::between(0, 1), read_line),
+    ("write", Arity::between(1, 2), write),
+    ("flush", Arity::between(0, 1), flush),
+    ("json/parse", Arity::at_least(1), json_parse),
+    ("json/write", Arity::at_least(1), json_write),
+    ("atom", Arity::exact(1), atom),
+    ("atom?", Arity::exact(1), is_atom),
+    ("deref", Arity::exact(1), deref),
+    ("reset!", Arity::exact(2), reset),
+    ("swap!", Arity::at_least(2), swap),
+    ("cons", Arity::exact(2), cons),
+    ("concat", Arity::at_least(0), concat),
+    ("vec", Arity::exact(1), vec),
+    ("nth", Arity::exact(2), nth),
+    ("first", Arity::exact(1), first),
+    ("rest", Arity::exact(1), rest),
+    ("throw", Arity::exact(1), throw),
+    ("ex-info", Arity::between(2, 3), ex_info),
+    ("ex-data", Arity::exact(1), ex_data),
+    ("ex-message", Arity::exact(1), ex_message),
+    ("ex-cause", Arity::exact(1), ex_cause),
+    ("stacktrace", Arity::exact(1), stacktrace),
+    ("apply", Arity::at_least(2), apply),
+    ("map", Arity::at_least(2), map),
+    ("mapv", Arity::at_least(2), mapv),
+    ("map-indexed", Arity::exact(2), map_indexed),
+    ("mapcat", Arity::at_least(2), mapcat),
+    ("keep", Arity::exact(2), keep),
+    ("filter", Arity::exact(2), filter),
+    ("take", Arity::exact(2), take),
+    ("drop", Arity::exact(2), drop),
+    ("range", Arity::between(0, 3), range),
+    ("iterate", Arity::exact(2), iterate),
+    ("repeat", Arity::between(1, 2), repeat),
+    ("cycle", Arity::exact(1), cycle),
+    ("doall", Arity::exact(1), doall),
+    ("dorun", Arity::exact(1), dorun),
+    ("realized?", Arity::exact(1), is_realized),
+    ("remove", Arity::exact(2), remove),
+    ("take-while", Arity::exact(2), take_while),
+    ("drop-while", Arity::exact(2), drop_while),
+    ("partition", Arity::between(2, 4), partition),
+    ("interleave", Arity::at_least(0), interleave),
+    ("distinct", Arity::exact(1), distinct),
+    ("reduce", Arity::between(2, 3), reduce),
+    ("reverse", Arity::exact(1), reverse),
+    ("last", Arity::exact(1), last),
+    ("butlast", Arity::exact(1), butlast),
+    ("every?", Arity::exact(2), is_every),
+    ("some", Arity::exact(2), some),
+    ("frequencies", Arity::exact(1), frequencies),
+    ("group-by", Arity::exact(2), group_by),
+    ("sort", Arity::between(1, 2), sort),
+    ("sort-by", Arity::between(2, 3), sort_by),
+    ("nil?", Arity::exact(1), is_nil),
+    ("true?", Arity::exact(1), is_true),
+    ("false?", Arity::exact(1), is_false),
+    ("symbol?", Arity::exact(1), is_symbol),
+    ("symbol", Arity::exact(1), symbol),
+    ("keyword", Arity::exact(1), keyword),
+    ("keyword?", Arity::exact(1), is_keyword),
+    ("vector", Arity::at_least(0), vector),
+    ("vector?", Arity::exact(1), is_vector),
+    ("sequential?", Arity::exact(1), is_sequential),
+    ("hash-map", Arity::at_least(0), hash_map),
+    ("map?", Arity::exact(1), is_map),
+    ("sorted-map", Arity::at_least(0), sorted_map),
+    ("sorted-map-by", Arity::at_least(1), sorted_map_by),
+    ("sorted?", Arity::exact(1), is_sorted),
+    ("hash-set", Arity::at_least(0), hash_set),
+    ("set", Arity::exact(1), set),
+    ("set?", Arity::exact(1), is_set),
+    ("disj", Arity::at_least(1), disj),
+    ("set/union", Arity::at_least(0), union),
+    ("set/intersection", Arity::at_least(1), intersection),
+    ("set/difference", Arity::at_least(1), difference),
+    ("set/subset?", Arity::exact(2), is_subset),
+    ("assoc", Arity::at_least(1), assoc),
+    ("dissoc", Arity::at_least(1), dissoc),
+    ("get", Arity::exact(2), get),
+    ("contains?", Arity::exact(2), is_contains),
+    ("keys", Arity::exact(1), keys),
+    ("vals", Arity::exact(1), vals),
+    ("readline", Arity::exact(1), readline),
+    ("time-ms", Arity::exact(0), time_ms),
+    ("meta", Arity::exact(1), meta),
+    ("with-meta", Arity::exact(2), with_meta),
+    ("fn?", Arity::exact(1), is_fn),
+    ("string?", Arity::exact(1), is_string),
+    ("subs", Arity::between(2, 3), subs),
+    ("str/split", Arity::between(2, 3), split),
+    ("str/join", Arity::between(1, 2), join),
+    ("str/trim", Arity::exact(1), trim),
+    ("str/upper-case", Arity::exact(1), upper_case),
+    ("str/lower-case", Arity::exact(1), lower_case),
+    ("str/replace", Arity::exact(3), replace),
+    ("str/starts-with?", Arity::exact(2), is_starts_with),
+    ("str/ends-with?", Arity::exact(2), is_ends_with),
+    ("str/includes?", Arity::exact(2), is_includes),
+    ("str/index-of", Arity::between(2, 3), index_of),
+    ("str/blank?", Arity::exact(1), is_blank),
+    ("format", Arity::at_least(1), format),
+    ("re-pattern", Arity::exact(1), re_pattern),
+    ("re-find", Arity::exact(2), re_find),
+    ("re-matches", Arity::exact(2), re_matches),
+    ("re-seq", Arity::exact(2), re_seq),
+    ("char?", Arity::exact(1), is_char),
+    ("char", Arity::exact(1), char),
+    ("number?", Arity::exact(1), is_number),
+    ("float?", Arity::exact(1), is_float),
+    ("int", Arity::exact(1), int),
+    ("double", Arity::exact(1), double),
+    ("ratio?", Arity::exact(1), is_ratio),
+    ("numerator", Arity::exact(1), numerator),
+    ("denominator", Arity::exact(1), denominator),
+    ("rationalize", Arity::exact(1), rationalize),
+    ("seq", Arity::exact(1), seq),
+    ("conj", Arity::at_least(1), conj),
+    ("macro?", Arity::exact(1), is_macro),
 ];
 
-fn add(args: &[Rc<MalVal>]) -> MalResult {
-    match (args[0].as_ref(), args[1].as_ref()) {
-        (MalVal::Integer(i), MalVal::Integer(j)) => Ok(Rc::new(MalVal::Integer(i + j))),
-        _ => unreachable!(),
+fn to_float(name: &str, v: &MalVal) -> Result<f64, MalError> {
+    match v {
+        MalVal::Integer(i) => Ok(*i as f64),
+        MalVal::BigInt(i) => Ok(i.to_f64().unwrap_or(f64::NAN)),
+        MalVal::Ratio(r) => Ok(r.to_f64().unwrap_or(f64::NAN)),
+        MalVal::Float(f) => Ok(*f),
+        v => Err(type_error(name, "number", v)),
     }
 }
-fn sub(args: &[Rc<MalVal>]) -> MalResult {
-    match (args[0].as_ref(), args[1].as_ref()) {
-        (MalVal::Integer(i), MalVal::Integer(j)) => Ok(Rc::new(MalVal::Integer(i - j))),
-        _ => unreachable!(),
+
+fn to_ratio(name: &str, v: &MalVal) -> Result<BigRational, MalError> {
+    match v {
+        MalVal::Ratio(r) => Ok(r.clone()),
+        _ => Ok(BigRational::from_integer(to_bigint(name, v)?)),
     }
 }
-fn mul(args: &[Rc<MalVal>]) -> MalResult {
-    match (args[0].as_ref(), args[1].as_ref()) {
-        (MalVal::Integer(i), MalVal::Integer(j)) => Ok(Rc::new(MalVal::Integer(i * j))),
-        _ => unreachable!(),
+
+fn to_bigint(name: &str, v: &MalVal) -> Result<BigInt, MalError> {
+    match v {
+        MalVal::Integer(i) => Ok(BigInt::from(*i)),
+        MalVal::BigInt(i) => Ok(i.clone()),
+        v => Err(type_error(name, "integer", v)),
     }
 }
-fn div(args: &[Rc<MalVal>]) -> MalResult {
-    match (args[0].as_ref(), args[1].as_ref()) {
-        (MalVal::Integer(i), MalVal::Integer(j)) => Ok(Rc::new(MalVal::Integer(i / j))),
-        _ => unreachable!(),
+
+fn type_error(name: &str, expected: &str, got: &MalVal) -> MalError {
+    MalError::raise(
+        "type-error",
+        format!("{name}: expected {expected}, got {}", got.type_name()),
+    )
+}
+
+fn realize_all(args: &[Rc<MalVal>]) -> Result<(), MalError> {
+    args.iter().try_for_each(|v| v.realize())
+}
+
+fn is_truthy(v: &MalVal) -> bool {
+    !matches!(v, MalVal::Nil | MalVal::Bool(false))
+}
+
+fn lazy(thunk: impl FnOnce() -> MalResult + 'static) -> Rc<MalVal> {
+    Rc::new(MalVal::LazySeq(Rc::new(LazySeq::new(thunk)), None))
+}
+
+fn lazy_cons(first: Rc<MalVal>, rest: Rc<MalVal>) -> Rc<MalVal> {
+    Rc::new(MalVal::LazySeq(Rc::new(LazySeq::cons(first, rest)), None))
+}
+
+fn lazy_or_eager(inputs: &[Rc<MalVal>], seq: Rc<MalVal>) -> MalResult {
+    if inputs
+        .iter()
+        .any(|v| matches!(v.as_ref(), MalVal::LazySeq(..)))
+    {
+        return Ok(seq);
+    }
+    Ok(Rc::new(MalVal::List(
+        seq.seq_iter()?.collect::<Result<_, _>>()?,
+        None,
+    )))
+}
+
+fn to_fn<'a>(name: &str, v: &'a MalVal) -> Result<&'a Rc<MalFn>, MalError> {
+    match v {
+        MalVal::Fn(f, _) => Ok(f),
+        v => Err(type_error(name, "function", v)),
+    }
+}
+
+fn items(name: &str, v: &MalVal) -> Result<SeqIter, MalError> {
+    match v {
+        MalVal::List(..)
+        | MalVal::Vector(..)
+        | MalVal::LazySeq(..)
+        | MalVal::Set(..)
+        | MalVal::String(_)
+        | MalVal::Nil => v.seq_iter(),
+        MalVal::HashMap(map, _) => Ok(SeqIter::Items(
+            sorted_entries(map)
+                .into_iter()
+                .map(|(k, v)| map_entry(k.into(), v.clone()))
+                .collect::<Vector<_>>()
+                .into_iter(),
+        )),
+        MalVal::SortedMap(map, _) => Ok(SeqIter::Items(
+            map.iter()
+                .map(|(k, v)| map_entry(k.clone(), v.clone()))
+                .collect::<Vector<_>>()
+                .into_iter(),
+        )),
+        v => Err(type_error(
+            name,
+            "list, vector, map, set, lazy-seq, string or nil",
+            v,
+        )),
+    }
+}
+
+fn map_entry(k: Rc<MalVal>, v: Rc<MalVal>) -> Rc<MalVal> {
+    Rc::new(MalVal::Vector(vector![k, v], None))
+}
+
+fn check_numbers(name: &str, args: &[Rc<MalVal>]) -> Result<(), MalError> {
+    match args.iter().find(|v| !v.is_number()) {
+        Some(v) => Err(type_error(name, "number", v)),
+        None => Ok(()),
     }
 }
 
+fn to_key(name: &str, k: &Rc<MalVal>) -> Result<Hashable, MalError> {
+    k.realize()?;
+    Hashable::try_from(k.clone()).map_err(|_| type_error(name, "hashable value", k))
+}
+
+fn arithmetic(
+    name: &str,
+    a: &MalVal,
+    b: &MalVal,
+    int_op: fn(i64, i64) -> Option<i64>,
+    big_op: fn(BigInt, BigInt) -> BigInt,
+    ratio_op: fn(BigRational, BigRational) -> BigRational,
+    float_op: fn(f64, f64) -> f64,
+) -> MalResult {
+    let result = match (a, b) {
+        (MalVal::Integer(i), MalVal::Integer(j)) => int_op(*i, *j).map_or_else(
+            || MalVal::BigInt(big_op(BigInt::from(*i), BigInt::from(*j))),
+            MalVal::Integer,
+        ),
+        (MalVal::Float(_), _) | (_, MalVal::Float(_)) => {
+            MalVal::Float(float_op(to_float(name, a)?, to_float(name, b)?))
+        }
+        (MalVal::Ratio(_), _) | (_, MalVal::Ratio(_)) => {
+            ratio_op(to_ratio(name, a)?, to_ratio(name, b)?).into()
+        }
+        _ => MalVal::BigInt(big_op(to_bigint(name, a)?, to_bigint(name, b)?)),
+    };
+    Ok(Rc::new(result))
+}
+
+fn divide(a: &MalVal, b: &MalVal) -> MalResult {
+    let zero_divisor = match b {
+        MalVal::Integer(j) => *j == 0,
+        MalVal::BigInt(j) => j.is_zero(),
+        _ => false,
+    };
+    if zero_divisor && !matches!(a, MalVal::Float(_)) {
+        return Err(MalError::raise(
+            "arithmetic-error",
+            "Divide by zero".to_string(),
+        ));
+    }
+    if let (MalVal::Integer(_) | MalVal::BigInt(_), MalVal::Integer(_) | MalVal::BigInt(_)) = (a, b)
+    {
+        let ratio = BigRational::new(to_bigint("/", a)?, to_bigint("/", b)?);
+        if !ratio.is_integer() {
+            return Ok(Rc::new(MalVal::Ratio(ratio)));
+        }
+    }
+    arithmetic(
+        "/",
+        a,
+        b,
+        i64::checked_div,
+        |i, j| i / j,
+        |i, j| i / j,
+        |i, j| i / j,
+    )
+}
+
+fn fold_numbers(
+    name: &str,
+    args: &[Rc<MalVal>],
+    identity: i64,
+    op: fn(&MalVal, &MalVal) -> MalResult,
+) -> MalResult {
+    check_numbers(name, args)?;
+    let (init, rest) = match args {
+        [first, rest @ ..] if !rest.is_empty() => (first.clone(), rest),
+        _ => (Rc::new(MalVal::Integer(identity)), args),
+    };
+    rest.iter().try_fold(init, |acc, v| op(&acc, v))
+}
+
+fn add(args: &[Rc<MalVal>]) -> MalResult {
+    fold_numbers("+", args, 0, |a, b| {
+        arithmetic(
+            "+",
+            a,
+            b,
+            i64::checked_add,
+            |i, j| i + j,
+            |i, j| i + j,
+            |i, j| i + j,
+        )
+    })
+}
+
+fn sub(args: &[Rc<MalVal>]) -> MalResult {
+    fold_numbers("-", args, 0, |a, b| {
+        arithmetic(
+            "-",
+            a,
+            b,
+            i64::checked_sub,
+            |i, j| i - j,
+            |i, j| i - j,
+            |i, j| i - j,
+        )
+    })
+}
+
+fn mul(args: &[Rc<MalVal>]) -> MalResult {
+    fold_numbers("*", args, 1, |a, b| {
+        arithmetic(
+            "*",
+            a,
+            b,
+            i64::checked_mul,
+            |i, j| i * j,
+            |i, j| i * j,
+            |i, j| i * j,
+        )
+    })
+}
+
+fn div(args: &[Rc<MalVal>]) -> MalResult {
+    fold_numbers("/", args, 1, divide)
+}
+
 fn prn(args: &[Rc<MalVal>]) -> MalResult {
-    println!(
-        "{}",
-        args.iter()
-            .map(|v| v.pr_str(true))
-            .collect::<Vec<_>>()
-            .join(" ")
-    );
+    realize_all(args)?;
+    let line = args
+        .iter()
+        .map(|v| v.pr_str(true))
+        .collect::<Vec<_>>()
+        .join(" ");
+    current_port("prn", "*out*")?.write_str(&format!("{line}\n"))?;
     Ok(Rc::new(MalVal::Nil))
 }
 
 fn list(args: &[Rc<MalVal>]) -> MalResult {
-    Ok(Rc::new(MalVal::List(args.to_vec(), None)))
+    Ok(Rc::new(MalVal::List(args.into(), None)))
 }
 
 fn is_list(args: &[Rc<MalVal>]) -> MalResult {
@@ -118,7 +432,13 @@ fn is_empty(args: &[Rc<MalVal>]) -> MalResult {
         MalVal::List(list, _) | MalVal::Vector(list, _) => {
             Ok(Rc::new(MalVal::Bool(list.is_empty())))
         }
-        _ => unreachable!(),
+        MalVal::HashMap(hashmap, _) => Ok(Rc::new(MalVal::Bool(hashmap.is_empty()))),
+        MalVal::SortedMap(map, _) => Ok(Rc::new(MalVal::Bool(map.is_empty()))),
+        MalVal::LazySeq(seq, _) => Ok(Rc::new(MalVal::Bool(seq.step()?.is_none()))),
+        MalVal::Set(set, _) => Ok(Rc::new(MalVal::Bool(set.is_empty()))),
+        MalVal::String(string) => Ok(Rc::new(MalVal::Bool(string.is_empty()))),
+        MalVal::Nil => Ok(Rc::new(MalVal::Bool(true))),
+        v => Err(type_error("empty?", "collection, string or nil", v)),
     }
 }
 
@@ -127,40 +447,67 @@ fn count(args: &[Rc<MalVal>]) -> MalResult {
         MalVal::List(list, _) | MalVal::Vector(list, _) => {
             Ok(Rc::new(MalVal::Integer(list.len().try_into().unwrap())))
         }
-        _ => Ok(Rc::new(MalVal::Integer(0))),
+        MalVal::HashMap(hashmap, _) => {
+            Ok(Rc::new(MalVal::Integer(hashmap.len().try_into().unwrap())))
+        }
+        MalVal::SortedMap(map, _) => Ok(Rc::new(MalVal::Integer(map.len().try_into().unwrap()))),
+        MalVal::LazySeq(..) => Ok(Rc::new(MalVal::Integer(
+            args[0].seq_iter()?.try_fold(0, |n, v| v.map(|_| n + 1))?,
+        ))),
+        MalVal::Set(set, _) => Ok(Rc::new(MalVal::Integer(set.len().try_into().unwrap()))),
+        MalVal::String(string) => Ok(Rc::new(MalVal::Integer(
+            string.chars().count().try_into().unwrap(),
+        ))),
+        MalVal::Nil => Ok(Rc::new(MalVal::Integer(0))),
+        v => Err(type_error("count", "collection, string or nil", v)),
     }
 }
 
 fn eq(args: &[Rc<MalVal>]) -> MalResult {
-    Ok(Rc::new(MalVal::Bool(args[0].as_ref() == args[1].as_ref())))
+    Ok(Rc::new(MalVal::Bool(args[0].equals(&args[1])?)))
 }
 
-fn lt(args: &[Rc<MalVal>]) -> MalResult {
-    match (args[0].as_ref(), args[1].as_ref()) {
-        (MalVal::Integer(i), MalVal::Integer(j)) => Ok(Rc::new(MalVal::Bool(i < j))),
-        _ => unreachable!(),
+fn compare(name: &str, a: &MalVal, b: &MalVal) -> Result<Option<Ordering>, MalError> {
+    Ok(match (a, b) {
+        (MalVal::Integer(i), MalVal::Integer(j)) => Some(i.cmp(j)),
+        (MalVal::Float(_), _) | (_, MalVal::Float(_)) => {
+            to_float(name, a)?.partial_cmp(&to_float(name, b)?)
+        }
+        (MalVal::Ratio(_), _) | (_, MalVal::Ratio(_)) => {
+            Some(to_ratio(name, a)?.cmp(&to_ratio(name, b)?))
+        }
+        _ => Some(to_bigint(name, a)?.cmp(&to_bigint(name, b)?)),
+    })
+}
+
+fn compare_chain(name: &str, args: &[Rc<MalVal>], pred: fn(Ordering) -> bool) -> MalResult {
+    check_numbers(name, args)?;
+    for w in args.windows(2) {
+        if !compare(name, &w[0], &w[1])?.is_some_and(pred) {
+            return Ok(Rc::new(MalVal::Bool(false)));
+        }
     }
+    Ok(Rc::new(MalVal::Bool(true)))
 }
+
+fn lt(args: &[Rc<MalVal>]) -> MalResult {
+    compare_chain("<", args, Ordering::is_lt)
+}
+
 fn lte(args: &[Rc<MalVal>]) -> MalResult {
-    match (args[0].as_ref(), args[1].as_ref()) {
-        (MalVal::Integer(i), MalVal::Integer(j)) => Ok(Rc::new(MalVal::Bool(i <= j))),
-        _ => unreachable!(),
-    }
+    compare_chain("<=", args, Ordering::is_le)
 }
+
 fn gt(args: &[Rc<MalVal>]) -> MalResult {
-    match (args[0].as_ref(), args[1].as_ref()) {
-        (MalVal::Integer(i), MalVal::Integer(j)) => Ok(Rc::new(MalVal::Bool(i > j))),
-        _ => unreachable!(),
-    }
+    compare_chain(">", args, Ordering::is_gt)
 }
+
 fn gte(args: &[Rc<MalVal>]) -> MalResult {
-    match (args[0].as_ref(), args[1].as_ref()) {
-        (MalVal::Integer(i), MalVal::Integer(j)) => Ok(Rc::new(MalVal::Bool(i >= j))),
-        _ => unreachable!(),
-    }
+    compare_chain(">=", args, Ordering::is_ge)
 }
 
 fn pr_str(args: &[Rc<MalVal>]) -> MalResult {
+    realize_all(args)?;
     Ok(Rc::new(MalVal::String(
         args.iter()
             .map(|v| v.pr_str(true))
@@ -170,6 +517,7 @@ fn pr_str(args: &[Rc<MalVal>]) -> MalResult {
 }
 
 fn str(args: &[Rc<MalVal>]) -> MalResult {
+    realize_all(args)?;
     Ok(Rc::new(MalVal::String(
         args.iter()
             .map(|v| v.pr_str(false))
@@ -179,30 +527,370 @@ fn str(args: &[Rc<MalVal>]) -> MalResult {
 }
 
 fn println(args: &[Rc<MalVal>]) -> MalResult {
-    println!(
-        "{}",
-        args.iter()
-            .map(|v| v.pr_str(false))
-            .collect::<Vec<_>>()
-            .join(" ")
-    );
+    realize_all(args)?;
+    let line = args
+        .iter()
+        .map(|v| v.pr_str(false))
+        .collect::<Vec<_>>()
+        .join(" ");
+    current_port("println", "*out*")?.write_str(&format!("{line}\n"))?;
     Ok(Rc::new(MalVal::Nil))
 }
 
 fn read_string(args: &[Rc<MalVal>]) -> MalResult {
     match args[0].as_ref() {
         MalVal::String(string) => Ok(Rc::new(read_str(string.as_str())?)),
-        _ => unreachable!(),
+        v => Err(type_error("read-string", "string", v)),
     }
 }
 
 fn slurp(args: &[Rc<MalVal>]) -> MalResult {
-    match args[0].as_ref() {
-        MalVal::String(file) => Ok(Rc::new(MalVal::String(
-            std::fs::read_to_string(file).unwrap(),
-        ))),
-        _ => unreachable!(),
+    let path = to_str("slurp", &args[0])?;
+    let content = fs::read_to_string(path).map_err(|e| MalError::io(path, e))?;
+    Ok(Rc::new(MalVal::String(content)))
+}
+
+fn option<'a>(
+    name: &str,
+    options: &'a [Rc<MalVal>],
+    key: &str,
+) -> Result<Option<&'a Rc<MalVal>>, MalError> {
+    if !options.len().is_multiple_of(2) {
+        return Err(MalError::raise(
+            "illegal-argument",
+            format!("{name}: options must be keyword/value pairs"),
+        ));
+    }
+    for pair in options.chunks(2) {
+        match pair[0].as_ref() {
+            MalVal::Keyword(k) if k == key => return Ok(Some(&pair[1])),
+            MalVal::Keyword(_) => {}
+            v => return Err(type_error(name, "keyword", v)),
+        }
     }
+    Ok(None)
+}
+
+fn spit(args: &[Rc<MalVal>]) -> MalResult {
+    let path = to_str("spit", &args[0])?;
+    let append = option("spit", &args[2..], "append")?.is_some_and(|v| is_truthy(v));
+    args[1].realize()?;
+    fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .append(append)
+        .truncate(!append)
+        .open(path)
+        .and_then(|mut file| file.write_all(args[1].pr_str(false).as_bytes()))
+        .map_err(|e| MalError::io(path, e))?;
+    Ok(Rc::new(MalVal::Nil))
+}
+
+fn is_file_exists(args: &[Rc<MalVal>]) -> MalResult {
+    let path = to_str("file-exists?", &args[0])?;
+    Ok(Rc::new(MalVal::Bool(Path::new(path).exists())))
+}
+
+fn delete_file(args: &[Rc<MalVal>]) -> MalResult {
+    let path = to_str("delete-file", &args[0])?;
+    let result = if Path::new(path).is_dir() {
+        fs::remove_dir(path)
+    } else {
+        fs::remove_file(path)
+    };
+    match result {
+        Ok(()) => Ok(Rc::new(MalVal::Bool(true))),
+        Err(_) if args.get(1).is_some_and(|v| is_truthy(v)) => Ok(Rc::new(MalVal::Bool(false))),
+        Err(e) => Err(MalError::io(path, e)),
+    }
+}
+
+fn list_dir(args: &[Rc<MalVal>]) -> MalResult {
+    let path = to_str("list-dir", &args[0])?;
+    let mut names = fs::read_dir(path)
+        .and_then(|entries| {
+            entries
+                .map(|entry| entry.map(|e| e.file_name().to_string_lossy().into_owned()))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .map_err(|e| MalError::io(path, e))?;
+    names.sort();
+    Ok(Rc::new(MalVal::List(
+        names
+            .into_iter()
+            .map(|name| Rc::new(MalVal::String(name)))
+            .collect(),
+        None,
+    )))
+}
+
+fn mkdir(args: &[Rc<MalVal>]) -> MalResult {
+    let path = to_str("mkdir", &args[0])?;
+    fs::create_dir_all(path).map_err(|e| MalError::io(path, e))?;
+    Ok(Rc::new(MalVal::Nil))
+}
+
+fn file_info(args: &[Rc<MalVal>]) -> MalResult {
+    let path = to_str("file-info", &args[0])?;
+    let metadata = fs::metadata(path).map_err(|e| MalError::io(path, e))?;
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map_or(MalVal::Nil, |d| MalVal::Integer(d.as_millis() as i64));
+    Ok(Rc::new(MalVal::HashMap(
+        HashMap::from(vec![
+            (
+                Hashable::keyword("size"),
+                Rc::new(MalVal::Integer(metadata.len() as i64)),
+            ),
+            (
+                Hashable::keyword("file?"),
+                Rc::new(MalVal::Bool(metadata.is_file())),
+            ),
+            (
+                Hashable::keyword("dir?"),
+                Rc::new(MalVal::Bool(metadata.is_dir())),
+            ),
+            (
+                Hashable::keyword("readonly?"),
+                Rc::new(MalVal::Bool(metadata.permissions().readonly())),
+            ),
+            (Hashable::keyword("modified"), Rc::new(modified)),
+        ]),
+        None,
+    )))
+}
+
+fn line_seq(args: &[Rc<MalVal>]) -> MalResult {
+    let port = match args[0].as_ref() {
+        MalVal::Port(port) => port.clone(),
+        MalVal::String(path) => {
+            let file = fs::File::open(path).map_err(|e| MalError::io(path, e))?;
+            Rc::new(Port::reader(path, BufReader::new(file)))
+        }
+        v => return Err(type_error("line-seq", "string or port", v)),
+    };
+    Ok(lines_lazy(port))
+}
+
+fn lines_lazy(port: Rc<Port>) -> Rc<MalVal> {
+    lazy(move || match port.read_line()? {
+        Some(line) => Ok(lazy_cons(Rc::new(MalVal::String(line)), lines_lazy(port))),
+        None => Ok(Rc::new(MalVal::Nil)),
+    })
+}
+
+fn to_port<'a>(name: &str, v: &'a MalVal) -> Result<&'a Rc<Port>, MalError> {
+    match v {
+        MalVal::Port(port) => Ok(port),
+        v => Err(type_error(name, "port", v)),
+    }
+}
+
+fn current_port(name: &str, var: &str) -> Result<Rc<Port>, MalError> {
+    let v = Env::dynamic(var).unwrap_or_else(|| Rc::new(MalVal::Nil));
+    to_port(name, &v).cloned()
+}
+
+fn open_reader(args: &[Rc<MalVal>]) -> MalResult {
+    let path = to_str("open-reader", &args[0])?;
+    let file = fs::File::open(path).map_err(|e| MalError::io(path, e))?;
+    Ok(Rc::new(MalVal::Port(Rc::new(Port::reader(
+        path,
+        BufReader::new(file),
+    )))))
+}
+
+fn open_writer(args: &[Rc<MalVal>]) -> MalResult {
+    let path = to_str("open-writer", &args[0])?;
+    let append = option("open-writer", &args[1..], "append")?.is_some_and(|v| is_truthy(v));
+    let file = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .append(append)
+        .truncate(!append)
+        .open(path)
+        .map_err(|e| MalError::io(path, e))?;
+    Ok(Rc::new(MalVal::Port(Rc::new(Port::writer(
+        path,
+        BufWriter::new(file),
+    )))))
+}
+
+fn string_reader(args: &[Rc<MalVal>]) -> MalResult {
+    let s = to_str("string-reader", &args[0])?;
+    Ok(Rc::new(MalVal::Port(Rc::new(Port::reader(
+        "string",
+        Cursor::new(s.to_string().into_bytes()),
+    )))))
+}
+
+fn string_writer(_: &[Rc<MalVal>]) -> MalResult {
+    Ok(Rc::new(MalVal::Port(Rc::new(Port::string_writer()))))
+}
+
+fn port_contents(args: &[Rc<MalVal>]) -> MalResult {
+    match to_port("port-contents", &args[0])?.contents() {
+        Some(contents) => Ok(Rc::new(MalVal::String(contents))),
+        None => Err(type_error("port-contents", "string-writer", &args[0])),
+    }
+}
+
+fn close(args: &[Rc<MalVal>]) -> MalResult {
+    to_port("close", &args[0])?.close()?;
+    Ok(Rc::new(MalVal::Nil))
+}
+
+fn is_port(args: &[Rc<MalVal>]) -> MalResult {
+    Ok(Rc::new(MalVal::Bool(matches!(
+        args[0].as_ref(),
+        MalVal::Port(_)
+    ))))
+}
+
+fn read_line(args: &[Rc<MalVal>]) -> MalResult {
+    let port = match args.first() {
+        Some(port) => to_port("read-line", port)?.clone(),
+        None => current_port("read-line", "*in*")?,
+    };
+    Ok(Rc::new(
+        port.read_line()?.map_or(MalVal::Nil, MalVal::String),
+    ))
+}
+
+fn write(args: &[Rc<MalVal>]) -> MalResult {
+    let (port, v) = match args {
+        [v] => (current_port("write", "*out*")?, v),
+        [port, v, ..] => (to_port("write", port)?.clone(), v),
+        [] => return Err(Arity::error("write", args.len())),
+    };
+    v.realize()?;
+    port.write_str(&v.pr_str(false))?;
+    Ok(Rc::new(MalVal::Nil))
+}
+
+fn flush(args: &[Rc<MalVal>]) -> MalResult {
+    match args.first() {
+        Some(port) => to_port("flush", port)?.flush()?,
+        None => current_port("flush", "*out*")?.flush()?,
+    }
+    Ok(Rc::new(MalVal::Nil))
+}
+
+fn json_parse(args: &[Rc<MalVal>]) -> MalResult {
+    let s = to_str("json/parse", &args[0])?;
+    let keywordize = option("json/parse", &args[1..], "keywordize")?.is_some_and(|v| is_truthy(v));
+    let value = serde_json::from_str(s)
+        .map_err(|e| MalError::raise("syntax-error", format!("json/parse: {e}")))?;
+    from_json(value, keywordize)
+}
+
+fn from_json(value: serde_json::Value, keywordize: bool) -> MalResult {
+    Ok(Rc::new(match value {
+        serde_json::Value::Null => MalVal::Nil,
+        serde_json::Value::Bool(b) => MalVal::Bool(b),
+        serde_json::Value::Number(n) => match n.as_i64() {
+            Some(i) => MalVal::Integer(i),
+            None => match n.as_str().parse::<BigInt>() {
+                Ok(i) => MalVal::BigInt(i),
+                Err(_) => MalVal::Float(n.as_str().parse().map_err(|_| {
+                    MalError::raise("syntax-error", format!("json/parse: invalid number {n}"))
+                })?),
+            },
+        },
+        serde_json::Value::String(s) => MalVal::String(s),
+        serde_json::Value::Array(items) => MalVal::Vector(
+            items
+                .into_iter()
+                .map(|v| from_json(v, keywordize))
+                .collect::<Result<_, _>>()?,
+            None,
+        ),
+        serde_json::Value::Object(entries) => MalVal::HashMap(
+            entries
+                .into_iter()
+                .map(|(k, v)| {
+                    let k = match keywordize {
+                        true => Hashable::keyword(&k),
+                        false => Hashable::string(&k),
+                    };
+                    Ok((k, from_json(v, keywordize)?))
+                })
+                .collect::<Result<_, MalError>>()?,
+            None,
+        ),
+    }))
+}
+
+fn json_write(args: &[Rc<MalVal>]) -> MalResult {
+    let pretty = option("json/write", &args[1..], "pretty")?.is_some_and(|v| is_truthy(v));
+    let value = to_json(&args[0])?;
+    let json = match pretty {
+        true => serde_json::to_string_pretty(&value),
+        false => serde_json::to_string(&value),
+    };
+    json.map(|s| Rc::new(MalVal::String(s)))
+        .map_err(|e| MalError::raise("type-error", format!("json/write: {e}")))
+}
+
+fn json_error(v: &MalVal) -> MalError {
+    MalError::raise(
+        "type-error",
+        format!("json/write: cannot encode {}", v.pr_str(true)),
+    )
+}
+
+fn json_key(k: &MalVal) -> Result<String, MalError> {
+    match k {
+        MalVal::String(s) | MalVal::Keyword(s) | MalVal::Symbol(s) => Ok(s.clone()),
+        k if k.is_number() => Ok(k.pr_str(false)),
+        MalVal::Char(c) => Ok(c.to_string()),
+        MalVal::Bool(b) => Ok(b.to_string()),
+        k => Err(json_error(k)),
+    }
+}
+
+fn to_json(v: &Rc<MalVal>) -> Result<serde_json::Value, MalError> {
+    Ok(match v.as_ref() {
+        MalVal::Nil => serde_json::Value::Null,
+        MalVal::Bool(b) => serde_json::Value::Bool(*b),
+        MalVal::Integer(i) => serde_json::Value::from(*i),
+        MalVal::BigInt(i) => {
+            serde_json::Value::Number(i.to_string().parse().map_err(|_| json_error(v))?)
+        }
+        MalVal::Float(_) | MalVal::Ratio(_) => {
+            serde_json::Number::from_f64(to_float("json/write", v)?)
+                .map(serde_json::Value::Number)
+                .ok_or_else(|| json_error(v))?
+        }
+        MalVal::String(s) | MalVal::Keyword(s) | MalVal::Symbol(s) => {
+            serde_json::Value::String(s.clone())
+        }
+        MalVal::Char(c) => serde_json::Value::String(c.to_string()),
+        MalVal::List(..) | MalVal::Vector(..) | MalVal::LazySeq(..) | MalVal::Set(..) => {
+            let mut items = Vec::new();
+            for item in v.seq_iter()? {
+                items.push(to_json(&item?)?);
+            }
+            serde_json::Value::Array(items)
+        }
+        MalVal::HashMap(map, _) => {
+            let mut object = serde_json::Map::new();
+            for (k, v) in sorted_entries(map) {
+                object.insert(json_key(&Rc::from(k))?, to_json(v)?);
+            }
+            serde_json::Value::Object(object)
+        }
+        MalVal::SortedMap(map, _) => {
+            let mut object = serde_json::Map::new();
+            for (k, v) in map.iter() {
+                object.insert(json_key(k)?, to_json(v)?);
+            }
+            serde_json::Value::Object(object)
+        }
+        _ => return Err(json_error(v)),
+    })
 }
 
 fn atom(args: &[Rc<MalVal>]) -> MalResult {
@@ -223,7 +911,7 @@ fn deref(args: &[Rc<MalVal>]) -> MalResult {
             v.set(m.clone());
             Ok(m)
         }
-        _ => unreachable!(),
+        v => Err(type_error("deref", "atom", v)),
     }
 }
 
@@ -233,7 +921,7 @@ fn reset(args: &[Rc<MalVal>]) -> MalResult {
             v.set(args[1].clone());
             Ok(args[1].clone())
         }
-        _ => unreachable!(),
+        v => Err(type_error("reset!", "atom", v)),
     }
 }
 
@@ -246,39 +934,63 @@ fn swap(args: &[Rc<MalVal>]) -> MalResult {
             v.set(result.clone());
             Ok(result)
         }
-        _ => unreachable!(),
+        (MalVal::Atom(_), f) => Err(type_error("swap!", "function", f)),
+        (v, _) => Err(type_error("swap!", "atom", v)),
     }
 }
 
 fn cons(args: &[Rc<MalVal>]) -> MalResult {
     match args[1].as_ref() {
         MalVal::List(list, _) | MalVal::Vector(list, _) => {
-            let mut buffer = vec![args[0].clone()];
-            buffer.append(&mut list.to_vec());
-            Ok(Rc::new(MalVal::List(buffer, None)))
+            let mut list = list.clone();
+            list.push_front(args[0].clone());
+            Ok(Rc::new(MalVal::List(list, None)))
         }
-        _ => unreachable!(),
+        MalVal::LazySeq(..) => Ok(lazy_cons(args[0].clone(), args[1].clone())),
+        v => Err(type_error("cons", "list, vector or lazy-seq", v)),
     }
 }
 
 fn concat(args: &[Rc<MalVal>]) -> MalResult {
-    let mut buffer = Vec::new();
+    let mut buffer = Vector::new();
     for v in args {
         match v.as_ref() {
             MalVal::List(list, _) | MalVal::Vector(list, _) => {
-                buffer.append(&mut list.to_vec());
+                buffer.append(list.clone());
             }
-            _ => unreachable!(),
+            MalVal::LazySeq(..) => {}
+            v => return Err(type_error("concat", "list, vector or lazy-seq", v)),
         }
     }
+    if args
+        .iter()
+        .any(|v| matches!(v.as_ref(), MalVal::LazySeq(..)))
+    {
+        return Ok(concat_lazy(SeqIter::Done, args.into()));
+    }
     Ok(Rc::new(MalVal::List(buffer, None)))
 }
 
+fn concat_lazy(mut current: SeqIter, mut colls: Vector<Rc<MalVal>>) -> Rc<MalVal> {
+    lazy(move || loop {
+        if let Some(v) = current.next().transpose()? {
+            return Ok(lazy_cons(v, concat_lazy(current, colls)));
+        }
+        match colls.pop_front() {
+            Some(coll) => current = coll.seq_iter()?,
+            None => return Ok(Rc::new(MalVal::Nil)),
+        }
+    })
+}
+
 fn vec(args: &[Rc<MalVal>]) -> MalResult {
     match args[0].as_ref() {
-        MalVal::List(list, _) => Ok(Rc::new(MalVal::Vector(list.to_vec(), None))),
+        MalVal::List(list, _) => Ok(Rc::new(MalVal::Vector(list.clone(), None))),
         MalVal::Vector(..) => Ok(args[0].clone()),
-        _ => unreachable!(),
+        v => Ok(Rc::new(MalVal::Vector(
+            items("vec", v)?.collect::<Result<_, _>>()?,
+            None,
+        ))),
     }
 }
 
@@ -288,62 +1000,696 @@ fn nth(args: &[Rc<MalVal>]) -> MalResult {
         | (MalVal::Vector(list, _), MalVal::Integer(i)) => list
             .get(*i as usize)
             .cloned()
-            .ok_or_else(|| MalError::Throw(Rc::new(MalVal::String("out of bounds".to_string())))),
-        _ => unreachable!(),
+            .ok_or_else(|| MalError::raise("index-out-of-bounds", "out of bounds".to_string())),
+        (MalVal::String(string), MalVal::Integer(i)) => string
+            .chars()
+            .nth(*i as usize)
+            .map(|c| Rc::new(MalVal::Char(c)))
+            .ok_or_else(|| MalError::raise("index-out-of-bounds", "out of bounds".to_string())),
+        (MalVal::LazySeq(..), MalVal::Integer(i)) => args[0]
+            .seq_iter()?
+            .nth(*i as usize)
+            .transpose()?
+            .ok_or_else(|| MalError::raise("index-out-of-bounds", "out of bounds".to_string())),
+        (MalVal::List(..) | MalVal::Vector(..) | MalVal::String(_) | MalVal::LazySeq(..), i) => {
+            Err(type_error("nth", "integer", i))
+        }
+        (v, _) => Err(type_error("nth", "list, vector, lazy-seq or string", v)),
+    }
+}
+
+fn first(args: &[Rc<MalVal>]) -> MalResult {
+    match args[0].as_ref() {
+        MalVal::List(list, _) | MalVal::Vector(list, _) => Ok(list
+            .front()
+            .map_or_else(|| Rc::new(MalVal::Nil), |v| v.clone())),
+        MalVal::String(string) => Ok(Rc::new(
+            string.chars().next().map_or(MalVal::Nil, MalVal::Char),
+        )),
+        MalVal::LazySeq(seq, _) => Ok(seq
+            .step()?
+            .map_or_else(|| Rc::new(MalVal::Nil), |(first, _)| first)),
+        MalVal::Nil => Ok(Rc::new(MalVal::Nil)),
+        v => Err(type_error(
+            "first",
+            "list, vector, lazy-seq, string or nil",
+            v,
+        )),
+    }
+}
+
+fn rest(args: &[Rc<MalVal>]) -> MalResult {
+    match args[0].as_ref() {
+        MalVal::List(list, _) | MalVal::Vector(list, _) => {
+            Ok(Rc::new(MalVal::List(list.skip(list.len().min(1)), None)))
+        }
+        MalVal::String(string) => Ok(Rc::new(MalVal::List(
+            string
+                .chars()
+                .skip(1)
+                .map(|c| Rc::new(MalVal::Char(c)))
+                .collect(),
+            None,
+        ))),
+        MalVal::LazySeq(seq, _) => Ok(seq.step()?.map_or_else(
+            || Rc::new(MalVal::List(Vector::new(), None)),
+            |(_, rest)| rest,
+        )),
+        MalVal::Nil => Ok(Rc::new(MalVal::List(Vector::new(), None))),
+        v => Err(type_error(
+            "rest",
+            "list, vector, lazy-seq, string or nil",
+            v,
+        )),
+    }
+}
+
+fn throw(args: &[Rc<MalVal>]) -> MalResult {
+    Err(MalError::throw(args[0].clone()))
+}
+
+fn ex_info(args: &[Rc<MalVal>]) -> MalResult {
+    match (args[0].as_ref(), args[1].as_ref()) {
+        (MalVal::String(message), MalVal::HashMap(..) | MalVal::Nil) => Ok(Rc::new(
+            MalVal::ex_info(message.clone(), args[1].clone(), args.get(2).cloned()),
+        )),
+        (MalVal::String(_), v) => Err(type_error("ex-info", "hash-map or nil", v)),
+        (v, _) => Err(type_error("ex-info", "string", v)),
+    }
+}
+
+fn ex_field(args: &[Rc<MalVal>], field: &str) -> MalResult {
+    Ok(args[0]
+        .ex_field(field)
+        .unwrap_or_else(|| Rc::new(MalVal::Nil)))
+}
+
+fn ex_data(args: &[Rc<MalVal>]) -> MalResult {
+    ex_field(args, "data")
+}
+
+fn ex_message(args: &[Rc<MalVal>]) -> MalResult {
+    match args[0].as_ref() {
+        MalVal::String(_) => Ok(args[0].clone()),
+        _ => ex_field(args, "message"),
+    }
+}
+
+fn ex_cause(args: &[Rc<MalVal>]) -> MalResult {
+    ex_field(args, "cause")
+}
+
+fn stacktrace(args: &[Rc<MalVal>]) -> MalResult {
+    Ok(args[0].stacktrace().unwrap_or_else(|| Rc::new(MalVal::Nil)))
+}
+
+fn apply(args: &[Rc<MalVal>]) -> MalResult {
+    match (args[0].as_ref(), args[args.len() - 1].as_ref()) {
+        (MalVal::Fn(f, _), MalVal::List(l, _)) | (MalVal::Fn(f, _), MalVal::Vector(l, _)) => {
+            let mut buffer = args[1..args.len() - 1].to_vec();
+            buffer.extend(l.iter().cloned());
+            f.run(&buffer)
+        }
+        (MalVal::Fn(f, _), MalVal::LazySeq(..)) => {
+            let mut buffer = args[1..args.len() - 1].to_vec();
+            for v in args[args.len() - 1].seq_iter()? {
+                buffer.push(v?);
+            }
+            f.run(&buffer)
+        }
+        (MalVal::Fn(..), l) => Err(type_error("apply", "list, vector or lazy-seq", l)),
+        (f, _) => Err(type_error("apply", "function", f)),
+    }
+}
+
+fn map(args: &[Rc<MalVal>]) -> MalResult {
+    let (f, colls) = map_args("map", args)?;
+    lazy_or_eager(&args[1..], map_lazy(f, colls))
+}
+
+fn mapv(args: &[Rc<MalVal>]) -> MalResult {
+    let (f, colls) = map_args("mapv", args)?;
+    Ok(Rc::new(MalVal::Vector(
+        map_lazy(f, colls).seq_iter()?.collect::<Result<_, _>>()?,
+        None,
+    )))
+}
+
+fn map_args(name: &str, args: &[Rc<MalVal>]) -> Result<(Rc<MalFn>, Vec<SeqIter>), MalError> {
+    let f = to_fn(name, &args[0])?.clone();
+    let colls = args[1..]
+        .iter()
+        .map(|v| items(name, v))
+        .collect::<Result<_, _>>()?;
+    Ok((f, colls))
+}
+
+fn next_round(colls: &mut [SeqIter]) -> Result<Option<Vec<Rc<MalVal>>>, MalError> {
+    let mut round = Vec::with_capacity(colls.len());
+    for items in colls.iter_mut() {
+        match items.next().transpose()? {
+            Some(v) => round.push(v),
+            None => return Ok(None),
+        }
+    }
+    Ok(Some(round))
+}
+
+fn map_lazy(f: Rc<MalFn>, mut colls: Vec<SeqIter>) -> Rc<MalVal> {
+    lazy(move || match next_round(&mut colls)? {
+        Some(args) => Ok(lazy_cons(f.run(&args)?, map_lazy(f, colls))),
+        None => Ok(Rc::new(MalVal::Nil)),
+    })
+}
+
+fn map_indexed(args: &[Rc<MalVal>]) -> MalResult {
+    let f = to_fn("map-indexed", &args[0])?;
+    let items = items("map-indexed", &args[1])?;
+    lazy_or_eager(&args[1..], map_indexed_lazy(f.clone(), 0, items))
+}
+
+fn map_indexed_lazy(f: Rc<MalFn>, i: i64, mut items: SeqIter) -> Rc<MalVal> {
+    lazy(move || match items.next().transpose()? {
+        Some(v) => Ok(lazy_cons(
+            f.run(&[Rc::new(MalVal::Integer(i)), v])?,
+            map_indexed_lazy(f, i + 1, items),
+        )),
+        None => Ok(Rc::new(MalVal::Nil)),
+    })
+}
+
+fn mapcat(args: &[Rc<MalVal>]) -> MalResult {
+    let (f, colls) = map_args("mapcat", args)?;
+    lazy_or_eager(&args[1..], mapcat_lazy(f, colls, SeqIter::Done))
+}
+
+fn mapcat_lazy(f: Rc<MalFn>, mut colls: Vec<SeqIter>, mut current: SeqIter) -> Rc<MalVal> {
+    lazy(move || loop {
+        if let Some(v) = current.next().transpose()? {
+            return Ok(lazy_cons(v, mapcat_lazy(f, colls, current)));
+        }
+        match next_round(&mut colls)? {
+            Some(args) => current = items("mapcat", &*f.run(&args)?)?,
+            None => return Ok(Rc::new(MalVal::Nil)),
+        }
+    })
+}
+
+fn keep(args: &[Rc<MalVal>]) -> MalResult {
+    let f = to_fn("keep", &args[0])?;
+    let items = items("keep", &args[1])?;
+    lazy_or_eager(&args[1..], keep_lazy(f.clone(), items))
+}
+
+fn keep_lazy(f: Rc<MalFn>, mut items: SeqIter) -> Rc<MalVal> {
+    lazy(move || {
+        while let Some(v) = items.next().transpose()? {
+            let result = f.run(&[v])?;
+            if !matches!(result.as_ref(), MalVal::Nil) {
+                return Ok(lazy_cons(result, keep_lazy(f, items)));
+            }
+        }
+        Ok(Rc::new(MalVal::Nil))
+    })
+}
+
+fn filter(args: &[Rc<MalVal>]) -> MalResult {
+    select("filter", args, true)
+}
+
+fn remove(args: &[Rc<MalVal>]) -> MalResult {
+    select("remove", args, false)
+}
+
+fn select(name: &str, args: &[Rc<MalVal>], keep: bool) -> MalResult {
+    let f = to_fn(name, &args[0])?;
+    let items = items(name, &args[1])?;
+    lazy_or_eager(&args[1..], filter_lazy(f.clone(), items, keep))
+}
+
+fn filter_lazy(f: Rc<MalFn>, mut items: SeqIter, keep: bool) -> Rc<MalVal> {
+    lazy(move || {
+        while let Some(v) = items.next().transpose()? {
+            if is_truthy(f.run(std::slice::from_ref(&v))?.as_ref()) == keep {
+                return Ok(lazy_cons(v, filter_lazy(f, items, keep)));
+            }
+        }
+        Ok(Rc::new(MalVal::Nil))
+    })
+}
+
+fn to_count(name: &str, v: &MalVal) -> Result<i64, MalError> {
+    match v {
+        MalVal::Integer(n) => Ok(*n),
+        v => Err(type_error(name, "integer", v)),
+    }
+}
+
+fn take(args: &[Rc<MalVal>]) -> MalResult {
+    let n = to_count("take", &args[0])?;
+    let mut items = items("take", &args[1])?;
+    if let MalVal::LazySeq(..) = args[1].as_ref() {
+        return Ok(take_lazy(n, items));
+    }
+    let mut buffer = Vector::new();
+    for _ in 0..n {
+        match items.next().transpose()? {
+            Some(v) => buffer.push_back(v),
+            None => break,
+        }
+    }
+    Ok(Rc::new(MalVal::List(buffer, None)))
+}
+
+fn take_lazy(n: i64, mut items: SeqIter) -> Rc<MalVal> {
+    lazy(move || {
+        if n <= 0 {
+            return Ok(Rc::new(MalVal::Nil));
+        }
+        match items.next().transpose()? {
+            Some(v) => Ok(lazy_cons(v, take_lazy(n - 1, items))),
+            None => Ok(Rc::new(MalVal::Nil)),
+        }
+    })
+}
+
+fn drop(args: &[Rc<MalVal>]) -> MalResult {
+    let n = to_count("drop", &args[0])?;
+    let mut items = items("drop", &args[1])?;
+    if let MalVal::LazySeq(..) = args[1].as_ref() {
+        return Ok(lazy(move || {
+            for _ in 0..n {
+                if items.next().transpose()?.is_none() {
+                    break;
+                }
+            }
+            Ok(iter_lazy(items))
+        }));
+    }
+    for _ in 0..n {
+        if items.next().transpose()?.is_none() {
+            break;
+        }
+    }
+    Ok(Rc::new(MalVal::List(
+        items.collect::<Result<_, _>>()?,
+        None,
+    )))
+}
+
+fn iter_lazy(mut items: SeqIter) -> Rc<MalVal> {
+    lazy(move || match items.next().transpose()? {
+        Some(v) => Ok(lazy_cons(v, iter_lazy(items))),
+        None => Ok(Rc::new(MalVal::Nil)),
+    })
+}
+
+fn range(args: &[Rc<MalVal>]) -> MalResult {
+    check_numbers("range", args)?;
+    let zero = Rc::new(MalVal::Integer(0));
+    let one = Rc::new(MalVal::Integer(1));
+    Ok(match args {
+        [] => range_from(zero, None, one),
+        [end] => range_from(zero, Some(end.clone()), one),
+        [start, end] => range_from(start.clone(), Some(end.clone()), one),
+        [start, end, step, ..] => range_from(start.clone(), Some(end.clone()), step.clone()),
+    })
+}
+
+fn range_from(start: Rc<MalVal>, end: Option<Rc<MalVal>>, step: Rc<MalVal>) -> Rc<MalVal> {
+    lazy(move || {
+        let more = match &end {
+            None => true,
+            Some(end) if compare("range", &step, &MalVal::Integer(0))? == Some(Ordering::Less) => {
+                compare("range", &start, end)? == Some(Ordering::Greater)
+            }
+            Some(end) => compare("range", &start, end)? == Some(Ordering::Less),
+        };
+        if !more {
+            return Ok(Rc::new(MalVal::Nil));
+        }
+        let next = add(&[start.clone(), step.clone()])?;
+        Ok(lazy_cons(start, range_from(next, end, step)))
+    })
+}
+
+fn iterate(args: &[Rc<MalVal>]) -> MalResult {
+    match args[0].as_ref() {
+        MalVal::Fn(f, _) => Ok(iterate_from(f.clone(), args[1].clone())),
+        v => Err(type_error("iterate", "function", v)),
+    }
+}
+
+fn iterate_from(f: Rc<MalFn>, x: Rc<MalVal>) -> Rc<MalVal> {
+    let next = x.clone();
+    lazy_cons(
+        x,
+        lazy(move || Ok(iterate_from(f.clone(), f.run(&[next])?))),
+    )
+}
+
+fn repeat(args: &[Rc<MalVal>]) -> MalResult {
+    match args {
+        [x] => Ok(repeat_from(None, x.clone())),
+        [n, x, ..] => Ok(repeat_from(Some(to_count("repeat", n)?), x.clone())),
+        [] => Err(Arity::error("repeat", args.len())),
+    }
+}
+
+fn repeat_from(n: Option<i64>, x: Rc<MalVal>) -> Rc<MalVal> {
+    lazy(move || match n {
+        Some(n) if n <= 0 => Ok(Rc::new(MalVal::Nil)),
+        _ => Ok(lazy_cons(x.clone(), repeat_from(n.map(|n| n - 1), x))),
+    })
+}
+
+fn cycle(args: &[Rc<MalVal>]) -> MalResult {
+    let items = items("cycle", &args[0])?;
+    Ok(cycle_from(args[0].clone(), items))
+}
+
+fn cycle_from(coll: Rc<MalVal>, mut items: SeqIter) -> Rc<MalVal> {
+    lazy(move || {
+        let v = match items.next().transpose()? {
+            Some(v) => v,
+            None => {
+                items = coll.seq_iter()?;
+                match items.next().transpose()? {
+                    Some(v) => v,
+                    None => return Ok(Rc::new(MalVal::Nil)),
+                }
+            }
+        };
+        Ok(lazy_cons(v, cycle_from(coll, items)))
+    })
+}
+
+fn doall(args: &[Rc<MalVal>]) -> MalResult {
+    items("doall", &args[0])?.try_for_each(|v| v.map(|_| ()))?;
+    Ok(args[0].clone())
+}
+
+fn dorun(args: &[Rc<MalVal>]) -> MalResult {
+    doall(args)?;
+    Ok(Rc::new(MalVal::Nil))
+}
+
+fn is_realized(args: &[Rc<MalVal>]) -> MalResult {
+    match args[0].as_ref() {
+        MalVal::LazySeq(seq, _) => Ok(Rc::new(MalVal::Bool(seq.is_realized()))),
+        v => Err(type_error("realized?", "lazy-seq", v)),
+    }
+}
+
+fn take_while(args: &[Rc<MalVal>]) -> MalResult {
+    let f = to_fn("take-while", &args[0])?;
+    let items = items("take-while", &args[1])?;
+    lazy_or_eager(&args[1..], take_while_lazy(f.clone(), items))
+}
+
+fn take_while_lazy(f: Rc<MalFn>, mut items: SeqIter) -> Rc<MalVal> {
+    lazy(move || match items.next().transpose()? {
+        Some(v) if is_truthy(f.run(std::slice::from_ref(&v))?.as_ref()) => {
+            Ok(lazy_cons(v, take_while_lazy(f, items)))
+        }
+        _ => Ok(Rc::new(MalVal::Nil)),
+    })
+}
+
+fn drop_while(args: &[Rc<MalVal>]) -> MalResult {
+    let f = to_fn("drop-while", &args[0])?.clone();
+    let mut items = items("drop-while", &args[1])?;
+    let seq = lazy(move || {
+        while let Some(v) = items.next().transpose()? {
+            if !is_truthy(f.run(std::slice::from_ref(&v))?.as_ref()) {
+                return Ok(lazy_cons(v, iter_lazy(items)));
+            }
+        }
+        Ok(Rc::new(MalVal::Nil))
+    });
+    lazy_or_eager(&args[1..], seq)
+}
+
+fn partition(args: &[Rc<MalVal>]) -> MalResult {
+    let (n, step, pad, coll) = match args {
+        [n, coll] => (
+            to_count("partition", n)?,
+            to_count("partition", n)?,
+            None,
+            coll,
+        ),
+        [n, step, coll] => (
+            to_count("partition", n)?,
+            to_count("partition", step)?,
+            None,
+            coll,
+        ),
+        [n, step, pad, coll, ..] => {
+            items("partition", pad)?;
+            (
+                to_count("partition", n)?,
+                to_count("partition", step)?,
+                Some(pad.clone()),
+                coll,
+            )
+        }
+        _ => return Err(Arity::error("partition", args.len())),
+    };
+    if n <= 0 || step <= 0 {
+        return Err(MalError::raise(
+            "illegal-argument",
+            "partition: size and step must be positive".to_string(),
+        ));
+    }
+    let items = items("partition", coll)?;
+    let seq = partition_lazy(n as usize, step as usize, pad, Vector::new(), 0, items);
+    lazy_or_eager(std::slice::from_ref(coll), seq)
+}
+
+fn partition_lazy(
+    n: usize,
+    step: usize,
+    pad: Option<Rc<MalVal>>,
+    mut window: Vector<Rc<MalVal>>,
+    skip: usize,
+    mut items: SeqIter,
+) -> Rc<MalVal> {
+    lazy(move || {
+        for _ in 0..skip {
+            if items.next().transpose()?.is_none() {
+                return Ok(Rc::new(MalVal::Nil));
+            }
+        }
+        while window.len() < n {
+            match items.next().transpose()? {
+                Some(v) => window.push_back(v),
+                None => break,
+            }
+        }
+        if window.len() < n {
+            let Some(pad) = pad.filter(|_| !window.is_empty()) else {
+                return Ok(Rc::new(MalVal::Nil));
+            };
+            for v in pad.seq_iter()?.take(n - window.len()) {
+                window.push_back(v?);
+            }
+            return Ok(lazy_cons(
+                Rc::new(MalVal::List(window, None)),
+                Rc::new(MalVal::Nil),
+            ));
+        }
+        let chunk = Rc::new(MalVal::List(window.clone(), None));
+        let rest = if step < n {
+            partition_lazy(n, step, pad, window.skip(step), 0, items)
+        } else {
+            partition_lazy(n, step, pad, Vector::new(), step - n, items)
+        };
+        Ok(lazy_cons(chunk, rest))
+    })
+}
+
+fn interleave(args: &[Rc<MalVal>]) -> MalResult {
+    let colls = args
+        .iter()
+        .map(|v| items("interleave", v))
+        .collect::<Result<Vec<_>, _>>()?;
+    lazy_or_eager(args, interleave_lazy(colls))
+}
+
+fn interleave_lazy(mut colls: Vec<SeqIter>) -> Rc<MalVal> {
+    lazy(move || match next_round(&mut colls)? {
+        Some(round) if !round.is_empty() => {
+            let rest = interleave_lazy(colls);
+            Ok(round
+                .into_iter()
+                .rev()
+                .fold(rest, |rest, v| lazy_cons(v, rest)))
+        }
+        _ => Ok(Rc::new(MalVal::Nil)),
+    })
+}
+
+fn distinct(args: &[Rc<MalVal>]) -> MalResult {
+    let items = items("distinct", &args[0])?;
+    lazy_or_eager(args, distinct_lazy(HashSet::new(), items))
+}
+
+fn distinct_lazy(mut seen: HashSet<Hashable>, mut items: SeqIter) -> Rc<MalVal> {
+    lazy(move || {
+        while let Some(v) = items.next().transpose()? {
+            if seen.insert(to_key("distinct", &v)?).is_none() {
+                return Ok(lazy_cons(v, distinct_lazy(seen, items)));
+            }
+        }
+        Ok(Rc::new(MalVal::Nil))
+    })
+}
+
+fn reduce(args: &[Rc<MalVal>]) -> MalResult {
+    let f = to_fn("reduce", &args[0])?;
+    let (mut acc, items) = match args {
+        [_, coll] => {
+            let mut items = items("reduce", coll)?;
+            match items.next().transpose()? {
+                Some(v) => (v, items),
+                None => return f.run(&[]),
+            }
+        }
+        [_, init, coll, ..] => (init.clone(), items("reduce", coll)?),
+        _ => return Err(Arity::error("reduce", args.len())),
+    };
+    for v in items {
+        acc = f.run(&[acc, v?])?;
+    }
+    Ok(acc)
+}
+
+fn reverse(args: &[Rc<MalVal>]) -> MalResult {
+    let mut buffer = Vector::new();
+    for v in items("reverse", &args[0])? {
+        buffer.push_front(v?);
+    }
+    Ok(Rc::new(MalVal::List(buffer, None)))
+}
+
+fn last(args: &[Rc<MalVal>]) -> MalResult {
+    let mut last = Rc::new(MalVal::Nil);
+    for v in items("last", &args[0])? {
+        last = v?;
+    }
+    Ok(last)
+}
+
+fn butlast(args: &[Rc<MalVal>]) -> MalResult {
+    let mut buffer = items("butlast", &args[0])?.collect::<Result<Vector<_>, _>>()?;
+    buffer.pop_back();
+    if buffer.is_empty() {
+        return Ok(Rc::new(MalVal::Nil));
+    }
+    Ok(Rc::new(MalVal::List(buffer, None)))
+}
+
+fn is_every(args: &[Rc<MalVal>]) -> MalResult {
+    let f = to_fn("every?", &args[0])?;
+    for v in items("every?", &args[1])? {
+        if !is_truthy(f.run(&[v?])?.as_ref()) {
+            return Ok(Rc::new(MalVal::Bool(false)));
+        }
+    }
+    Ok(Rc::new(MalVal::Bool(true)))
+}
+
+fn some(args: &[Rc<MalVal>]) -> MalResult {
+    let f = to_fn("some", &args[0])?;
+    for v in items("some", &args[1])? {
+        let result = f.run(&[v?])?;
+        if is_truthy(result.as_ref()) {
+            return Ok(result);
+        }
     }
+    Ok(Rc::new(MalVal::Nil))
 }
 
-fn first(args: &[Rc<MalVal>]) -> MalResult {
-    match args[0].as_ref() {
-        MalVal::List(list, _) | MalVal::Vector(list, _) => Ok(list
-            .first()
-            .map_or_else(|| Rc::new(MalVal::Nil), |v| v.clone())),
-        MalVal::Nil => Ok(Rc::new(MalVal::Nil)),
-        _ => unreachable!(),
+fn frequencies(args: &[Rc<MalVal>]) -> MalResult {
+    let mut counts = HashMap::<Hashable, i64>::new();
+    for v in items("frequencies", &args[0])? {
+        *counts.entry(to_key("frequencies", &v?)?).or_insert(0) += 1;
     }
+    Ok(Rc::new(MalVal::HashMap(
+        counts
+            .into_iter()
+            .map(|(k, n)| (k, Rc::new(MalVal::Integer(n))))
+            .collect(),
+        None,
+    )))
 }
 
-fn rest(args: &[Rc<MalVal>]) -> MalResult {
-    match args[0].as_ref() {
-        MalVal::List(list, _) | MalVal::Vector(list, _) => {
-            let mut iter = list.iter();
-            iter.next();
-            Ok(Rc::new(MalVal::List(iter.cloned().collect(), None)))
-        }
-        MalVal::Nil => Ok(Rc::new(MalVal::List(Vec::new(), None))),
-        _ => unreachable!(),
+fn group_by(args: &[Rc<MalVal>]) -> MalResult {
+    let f = to_fn("group-by", &args[0])?;
+    let mut groups = HashMap::<Hashable, Vector<Rc<MalVal>>>::new();
+    for v in items("group-by", &args[1])? {
+        let v = v?;
+        let key = to_key("group-by", &f.run(std::slice::from_ref(&v))?)?;
+        groups.entry(key).or_default().push_back(v);
     }
+    Ok(Rc::new(MalVal::HashMap(
+        groups
+            .into_iter()
+            .map(|(k, group)| (k, Rc::new(MalVal::Vector(group, None))))
+            .collect(),
+        None,
+    )))
 }
 
-fn throw(args: &[Rc<MalVal>]) -> MalResult {
-    Err(MalError::Throw(args[0].clone()))
+fn sort(args: &[Rc<MalVal>]) -> MalResult {
+    let (comparator, coll) = match args {
+        [coll] => (None, coll),
+        [comparator, coll, ..] => (Some(comparator), coll),
+        [] => return Err(Arity::error("sort", args.len())),
+    };
+    let items = items("sort", coll)?.collect::<Result<Vec<_>, _>>()?;
+    let sorted = merge_sort(items, &mut |a, b| compare_with(comparator, a, b))?;
+    Ok(Rc::new(MalVal::List(sorted.into(), None)))
 }
 
-fn apply(args: &[Rc<MalVal>]) -> MalResult {
-    match (args.first(), args.last()) {
-        (Some(f), Some(l)) => match (f.as_ref(), l.as_ref()) {
-            (MalVal::Fn(f, _), MalVal::List(l, _)) | (MalVal::Fn(f, _), MalVal::Vector(l, _)) => {
-                let mut buffer = args[1..args.len() - 1].to_vec();
-                buffer.append(&mut l.to_vec());
-                f.run(&buffer)
-            }
-            _ => unreachable!(),
-        },
-        _ => unreachable!(),
-    }
+fn sort_by(args: &[Rc<MalVal>]) -> MalResult {
+    let (keyfn, comparator, coll) = match args {
+        [keyfn, coll] => (keyfn, None, coll),
+        [keyfn, comparator, coll, ..] => (keyfn, Some(comparator), coll),
+        _ => return Err(Arity::error("sort-by", args.len())),
+    };
+    let keyfn = to_fn("sort-by", keyfn)?;
+    let keyed = items("sort-by", coll)?
+        .map(|v| v.and_then(|v| Ok((keyfn.run(std::slice::from_ref(&v))?, v))))
+        .collect::<Result<Vec<_>, _>>()?;
+    let sorted = merge_sort(keyed, &mut |(a, _), (b, _)| compare_with(comparator, a, b))?;
+    Ok(Rc::new(MalVal::List(
+        sorted.into_iter().map(|(_, v)| v).collect(),
+        None,
+    )))
 }
 
-fn map(args: &[Rc<MalVal>]) -> MalResult {
-    match (args[0].as_ref(), args[1].as_ref()) {
-        (MalVal::Fn(f, _), MalVal::List(l, _)) | (MalVal::Fn(f, _), MalVal::Vector(l, _)) => {
-            let mut buffer = Vec::with_capacity(l.len());
-            for v in l {
-                buffer.push(f.run(&[v.clone()])?);
-            }
-            Ok(Rc::new(MalVal::List(buffer, None)))
+fn merge_sort<T>(
+    mut items: Vec<T>,
+    cmp: &mut impl FnMut(&T, &T) -> Result<Ordering, MalError>,
+) -> Result<Vec<T>, MalError> {
+    if items.len() <= 1 {
+        return Ok(items);
+    }
+    let right = items.split_off(items.len() / 2);
+    let mut left = merge_sort(items, cmp)?.into_iter().peekable();
+    let mut right = merge_sort(right, cmp)?.into_iter().peekable();
+    let mut merged = Vec::new();
+    while let (Some(a), Some(b)) = (left.peek(), right.peek()) {
+        if cmp(b, a)? == Ordering::Less {
+            merged.extend(right.next());
+        } else {
+            merged.extend(left.next());
         }
-        _ => unreachable!(),
     }
+    merged.extend(left);
+    merged.extend(right);
+    Ok(merged)
 }
 
 fn is_nil(args: &[Rc<MalVal>]) -> MalResult {
@@ -377,7 +1723,7 @@ fn is_symbol(args: &[Rc<MalVal>]) -> MalResult {
 fn symbol(args: &[Rc<MalVal>]) -> MalResult {
     match args[0].as_ref() {
         MalVal::String(s) => Ok(Rc::new(MalVal::Symbol(s.clone()))),
-        _ => unreachable!(),
+        v => Err(type_error("symbol", "string", v)),
     }
 }
 
@@ -385,7 +1731,7 @@ fn keyword(args: &[Rc<MalVal>]) -> MalResult {
     match args[0].as_ref() {
         MalVal::String(s) => Ok(Rc::new(MalVal::Keyword(s.clone()))),
         MalVal::Keyword(_) => Ok(args[0].clone()),
-        _ => unreachable!(),
+        v => Err(type_error("keyword", "string or keyword", v)),
     }
 }
 
@@ -397,7 +1743,7 @@ fn is_keyword(args: &[Rc<MalVal>]) -> MalResult {
 }
 
 fn vector(args: &[Rc<MalVal>]) -> MalResult {
-    Ok(Rc::new(MalVal::Vector(args.to_vec(), None)))
+    Ok(Rc::new(MalVal::Vector(args.into(), None)))
 }
 
 fn is_vector(args: &[Rc<MalVal>]) -> MalResult {
@@ -409,7 +1755,9 @@ fn is_vector(args: &[Rc<MalVal>]) -> MalResult {
 
 fn is_sequential(args: &[Rc<MalVal>]) -> MalResult {
     match args[0].as_ref() {
-        MalVal::Vector(..) | MalVal::List(..) => Ok(Rc::new(MalVal::Bool(true))),
+        MalVal::Vector(..) | MalVal::List(..) | MalVal::LazySeq(..) => {
+            Ok(Rc::new(MalVal::Bool(true)))
+        }
         _ => Ok(Rc::new(MalVal::Bool(false))),
     }
 }
@@ -418,7 +1766,7 @@ fn hash_map(args: &[Rc<MalVal>]) -> MalResult {
     let mut hashmap = HashMap::new();
     let mut iter = args.iter();
     while let (Some(k), Some(v)) = (iter.next(), iter.next()) {
-        let k = k.as_ref().into();
+        let k = to_key("hash-map", k)?;
         hashmap.insert(k, v.clone());
     }
     Ok(Rc::new(MalVal::HashMap(hashmap, None)))
@@ -426,98 +1774,219 @@ fn hash_map(args: &[Rc<MalVal>]) -> MalResult {
 
 fn is_map(args: &[Rc<MalVal>]) -> MalResult {
     match args[0].as_ref() {
-        MalVal::HashMap(..) => Ok(Rc::new(MalVal::Bool(true))),
+        MalVal::HashMap(..) | MalVal::SortedMap(..) => Ok(Rc::new(MalVal::Bool(true))),
         _ => Ok(Rc::new(MalVal::Bool(false))),
     }
 }
 
-fn assoc(args: &[Rc<MalVal>]) -> MalResult {
+fn sorted_map_from(mut map: SortedMap, meta: Option<Rc<MalVal>>, args: &[Rc<MalVal>]) -> MalResult {
     let mut iter = args.iter();
-    let mut hashmap = match iter.next() {
-        Some(h) => match h.as_ref() {
-            MalVal::HashMap(h, _) => h.clone(),
-            _ => unreachable!(),
-        },
-        _ => unreachable!(),
+    while let (Some(k), Some(v)) = (iter.next(), iter.next()) {
+        map.insert(k.clone(), v.clone())?;
+    }
+    Ok(Rc::new(MalVal::SortedMap(map, meta)))
+}
+
+fn sorted_map(args: &[Rc<MalVal>]) -> MalResult {
+    sorted_map_from(SortedMap::new(None), None, args)
+}
+
+fn sorted_map_by(args: &[Rc<MalVal>]) -> MalResult {
+    match args[0].as_ref() {
+        MalVal::Fn(..) => sorted_map_from(SortedMap::new(Some(args[0].clone())), None, &args[1..]),
+        v => Err(type_error("sorted-map-by", "function", v)),
+    }
+}
+
+fn is_sorted(args: &[Rc<MalVal>]) -> MalResult {
+    match args[0].as_ref() {
+        MalVal::SortedMap(..) => Ok(Rc::new(MalVal::Bool(true))),
+        _ => Ok(Rc::new(MalVal::Bool(false))),
+    }
+}
+
+fn hash_set(args: &[Rc<MalVal>]) -> MalResult {
+    let mut set = HashSet::new();
+    for k in args {
+        set.insert(to_key("hash-set", k)?);
+    }
+    Ok(Rc::new(MalVal::Set(set, None)))
+}
+
+fn set(args: &[Rc<MalVal>]) -> MalResult {
+    if let MalVal::Set(..) = args[0].as_ref() {
+        return Ok(args[0].clone());
+    }
+    let mut set = HashSet::new();
+    for k in items("set", &args[0])? {
+        set.insert(to_key("set", &k?)?);
+    }
+    Ok(Rc::new(MalVal::Set(set, None)))
+}
+
+fn is_set(args: &[Rc<MalVal>]) -> MalResult {
+    match args[0].as_ref() {
+        MalVal::Set(..) => Ok(Rc::new(MalVal::Bool(true))),
+        _ => Ok(Rc::new(MalVal::Bool(false))),
+    }
+}
+
+fn disj(args: &[Rc<MalVal>]) -> MalResult {
+    match args[0].as_ref() {
+        MalVal::Set(set, data) => {
+            let mut set = set.clone();
+            for k in &args[1..] {
+                set.remove(&to_key("disj", k)?);
+            }
+            Ok(Rc::new(MalVal::Set(set, data.clone())))
+        }
+        MalVal::Nil => Ok(args[0].clone()),
+        v => Err(type_error("disj", "set or nil", v)),
+    }
+}
+
+fn to_set<'a>(name: &str, v: &'a MalVal) -> Result<&'a HashSet<Hashable>, MalError> {
+    match v {
+        MalVal::Set(set, _) => Ok(set),
+        v => Err(type_error(name, "set", v)),
+    }
+}
+
+fn union(args: &[Rc<MalVal>]) -> MalResult {
+    let mut set = HashSet::new();
+    for v in args {
+        set.extend(to_set("set/union", v)?.iter().cloned());
+    }
+    Ok(Rc::new(MalVal::Set(set, None)))
+}
+
+fn intersection(args: &[Rc<MalVal>]) -> MalResult {
+    let mut set = to_set("set/intersection", &args[0])?.clone();
+    for v in &args[1..] {
+        let other = to_set("set/intersection", v)?;
+        set.retain(|k| other.contains(k));
+    }
+    Ok(Rc::new(MalVal::Set(set, None)))
+}
+
+fn difference(args: &[Rc<MalVal>]) -> MalResult {
+    let mut set = to_set("set/difference", &args[0])?.clone();
+    for v in &args[1..] {
+        let other = to_set("set/difference", v)?;
+        set.retain(|k| !other.contains(k));
+    }
+    Ok(Rc::new(MalVal::Set(set, None)))
+}
+
+fn is_subset(args: &[Rc<MalVal>]) -> MalResult {
+    let set = to_set("set/subset?", &args[0])?;
+    let other = to_set("set/subset?", &args[1])?;
+    Ok(Rc::new(MalVal::Bool(set.is_subset(other))))
+}
+
+fn assoc(args: &[Rc<MalVal>]) -> MalResult {
+    let (mut hashmap, meta) = match args[0].as_ref() {
+        MalVal::HashMap(h, meta) => (h.clone(), meta.clone()),
+        MalVal::SortedMap(map, meta) => {
+            return sorted_map_from(map.clone(), meta.clone(), &args[1..])
+        }
+        v => return Err(type_error("assoc", "hash-map", v)),
     };
+    let mut iter = args[1..].iter();
     while let (Some(k), Some(v)) = (iter.next(), iter.next()) {
-        let k = k.as_ref().into();
+        let k = to_key("assoc", k)?;
         hashmap.insert(k, v.clone());
     }
-    Ok(Rc::new(MalVal::HashMap(hashmap, None)))
+    Ok(Rc::new(MalVal::HashMap(hashmap, meta)))
 }
 
 fn dissoc(args: &[Rc<MalVal>]) -> MalResult {
-    let mut iter = args.iter();
-    let mut hashmap = match iter.next() {
-        Some(h) => match h.as_ref() {
-            MalVal::HashMap(h, _) => h.clone(),
-            _ => unreachable!(),
-        },
-        _ => unreachable!(),
+    let (mut hashmap, meta) = match args[0].as_ref() {
+        MalVal::HashMap(h, meta) => (h.clone(), meta.clone()),
+        MalVal::SortedMap(map, meta) => {
+            let mut map = map.clone();
+            for k in &args[1..] {
+                map.remove(k)?;
+            }
+            return Ok(Rc::new(MalVal::SortedMap(map, meta.clone())));
+        }
+        v => return Err(type_error("dissoc", "hash-map", v)),
     };
-    for k in iter {
-        let k = k.as_ref().into();
+    for k in &args[1..] {
+        let k = to_key("dissoc", k)?;
         hashmap.remove(&k);
     }
-    Ok(Rc::new(MalVal::HashMap(hashmap, None)))
+    Ok(Rc::new(MalVal::HashMap(hashmap, meta)))
 }
 
 fn get(args: &[Rc<MalVal>]) -> MalResult {
     match args[0].as_ref() {
         MalVal::HashMap(h, _) => {
-            let k = args[1].as_ref().into();
+            let k = to_key("get", &args[1])?;
             Ok(h.get(&k)
                 .map_or_else(|| Rc::new(MalVal::Nil), |v| v.clone()))
         }
+        MalVal::SortedMap(map, _) => Ok(map.get(&args[1])?.unwrap_or_else(|| Rc::new(MalVal::Nil))),
         MalVal::Nil => Ok(args[0].clone()),
-        _ => unreachable!(),
+        v => Err(type_error("get", "hash-map or nil", v)),
     }
 }
 
 fn is_contains(args: &[Rc<MalVal>]) -> MalResult {
     match args[0].as_ref() {
         MalVal::HashMap(h, _) => {
-            let k = args[1].as_ref().into();
+            let k = to_key("contains?", &args[1])?;
             Ok(Rc::new(MalVal::Bool(h.contains_key(&k))))
         }
-        _ => unreachable!(),
+        MalVal::SortedMap(map, _) => Ok(Rc::new(MalVal::Bool(map.get(&args[1])?.is_some()))),
+        MalVal::Set(set, _) => {
+            let k = to_key("contains?", &args[1])?;
+            Ok(Rc::new(MalVal::Bool(set.contains(&k))))
+        }
+        v => Err(type_error("contains?", "hash-map or set", v)),
     }
 }
 
 fn keys(args: &[Rc<MalVal>]) -> MalResult {
     match args[0].as_ref() {
         MalVal::HashMap(h, _) => Ok(Rc::new(MalVal::List(
-            h.keys().map(|k| Rc::new(k.into())).collect(),
+            sorted_entries(h)
+                .into_iter()
+                .map(|(k, _)| k.into())
+                .collect(),
             None,
         ))),
-        _ => unreachable!(),
+        MalVal::SortedMap(map, _) => Ok(Rc::new(MalVal::List(
+            map.iter().map(|(k, _)| k.clone()).collect(),
+            None,
+        ))),
+        v => Err(type_error("keys", "hash-map", v)),
     }
 }
 
 fn vals(args: &[Rc<MalVal>]) -> MalResult {
     match args[0].as_ref() {
-        MalVal::HashMap(h, _) => Ok(Rc::new(MalVal::List(h.values().cloned().collect(), None))),
-        _ => unreachable!(),
+        MalVal::HashMap(h, _) => Ok(Rc::new(MalVal::List(
+            sorted_entries(h)
+                .into_iter()
+                .map(|(_, v)| v.clone())
+                .collect(),
+            None,
+        ))),
+        MalVal::SortedMap(map, _) => Ok(Rc::new(MalVal::List(
+            map.iter().map(|(_, v)| v.clone()).collect(),
+            None,
+        ))),
+        v => Err(type_error("vals", "hash-map", v)),
     }
 }
 
 fn readline(args: &[Rc<MalVal>]) -> MalResult {
-    match args[0].as_ref() {
-        MalVal::String(s) => {
-            let mut buffer = String::new();
-            print!("{s}");
-            std::io::stdout().flush().unwrap();
-            match std::io::stdin().read_line(&mut buffer) {
-                Ok(n) if n > 0 => {
-                    buffer.pop();
-                    Ok(Rc::new(MalVal::String(buffer)))
-                }
-                _ => Ok(Rc::new(MalVal::Nil)),
-            }
-        }
-        _ => unreachable!(),
-    }
+    let prompt = to_str("readline", &args[0])?;
+    let out = current_port("readline", "*out*")?;
+    out.write_str(prompt)?;
+    out.flush()?;
+    read_line(&[])
 }
 
 fn meta(args: &[Rc<MalVal>]) -> MalResult {
@@ -525,55 +1994,450 @@ fn meta(args: &[Rc<MalVal>]) -> MalResult {
         MalVal::List(_, data)
         | MalVal::Vector(_, data)
         | MalVal::HashMap(_, data)
+        | MalVal::SortedMap(_, data)
+        | MalVal::LazySeq(_, data)
+        | MalVal::Set(_, data)
         | MalVal::Fn(_, data) => data
             .as_ref()
             .cloned()
             .map_or_else(|| Ok(Rc::new(MalVal::Nil)), Ok),
-        _ => unreachable!(),
+        _ => Ok(Rc::new(MalVal::Nil)),
     }
 }
 
 fn with_meta(args: &[Rc<MalVal>]) -> MalResult {
     match args[0].as_ref() {
-        MalVal::List(list, _) => Ok(Rc::new(MalVal::List(list.to_vec(), Some(args[1].clone())))),
+        MalVal::List(list, _) => Ok(Rc::new(MalVal::List(list.clone(), Some(args[1].clone())))),
         MalVal::Vector(vector, _) => Ok(Rc::new(MalVal::Vector(
-            vector.to_vec(),
+            vector.clone(),
             Some(args[1].clone()),
         ))),
         MalVal::HashMap(hashmap, _) => Ok(Rc::new(MalVal::HashMap(
             hashmap.clone(),
             Some(args[1].clone()),
         ))),
+        MalVal::Set(set, _) => Ok(Rc::new(MalVal::Set(set.clone(), Some(args[1].clone())))),
+        MalVal::SortedMap(map, _) => Ok(Rc::new(MalVal::SortedMap(
+            map.clone(),
+            Some(args[1].clone()),
+        ))),
+        MalVal::LazySeq(seq, _) => Ok(Rc::new(MalVal::LazySeq(seq.clone(), Some(args[1].clone())))),
         MalVal::Fn(func, _) => Ok(Rc::new(MalVal::Fn(func.clone(), Some(args[1].clone())))),
-        _ => unreachable!(),
+        v => Err(type_error("with-meta", "collection or function", v)),
     }
 }
 
 fn time_ms(_: &[Rc<MalVal>]) -> MalResult {
+    let elapsed = std::time::SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|_| {
+            MalError::raise(
+                "illegal-state",
+                "time-ms: system clock is before the epoch".to_string(),
+            )
+        })?;
     Ok(Rc::new(MalVal::Integer(
-        std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_millis()
-            .try_into()
-            .unwrap(),
+        elapsed.as_millis().try_into().unwrap_or(i64::MAX),
     )))
 }
 
 fn conj(args: &[Rc<MalVal>]) -> MalResult {
     match args[0].as_ref() {
         MalVal::List(list, data) => {
-            let mut buffer = args[1..].iter().rev().cloned().collect::<Vec<_>>();
-            buffer.append(&mut list.to_vec());
-            Ok(Rc::new(MalVal::List(buffer, data.clone())))
+            let mut list = list.clone();
+            for v in &args[1..] {
+                list.push_front(v.clone());
+            }
+            Ok(Rc::new(MalVal::List(list, data.clone())))
         }
         MalVal::Vector(vector, data) => {
-            let mut buffer = vector.to_vec();
-            buffer.append(&mut args[1..].to_vec());
-            Ok(Rc::new(MalVal::Vector(buffer, data.clone())))
+            let mut vector = vector.clone();
+            vector.extend(args[1..].iter().cloned());
+            Ok(Rc::new(MalVal::Vector(vector, data.clone())))
+        }
+        MalVal::Set(set, data) => {
+            let mut set = set.clone();
+            for k in &args[1..] {
+                set.insert(to_key("conj", k)?);
+            }
+            Ok(Rc::new(MalVal::Set(set, data.clone())))
+        }
+        MalVal::LazySeq(..) => Ok(args[1..]
+            .iter()
+            .fold(args[0].clone(), |seq, v| lazy_cons(v.clone(), seq))),
+        v => Err(type_error("conj", "list, vector, lazy-seq or set", v)),
+    }
+}
+
+fn to_str<'a>(name: &str, v: &'a MalVal) -> Result<&'a str, MalError> {
+    match v {
+        MalVal::String(s) => Ok(s),
+        v => Err(type_error(name, "string", v)),
+    }
+}
+
+fn char_offset(s: &str, index: usize) -> Option<usize> {
+    s.char_indices()
+        .map(|(i, _)| i)
+        .chain(std::iter::once(s.len()))
+        .nth(index)
+}
+
+fn subs(args: &[Rc<MalVal>]) -> MalResult {
+    let s = to_str("subs", &args[0])?;
+    let start = to_count("subs", &args[1])?;
+    let end = match args.get(2) {
+        Some(end) => to_count("subs", end)?,
+        None => s.chars().count() as i64,
+    };
+    let offsets = usize::try_from(start)
+        .ok()
+        .zip(usize::try_from(end).ok())
+        .filter(|(start, end)| start <= end)
+        .and_then(|(start, end)| char_offset(s, start).zip(char_offset(s, end)));
+    match offsets {
+        Some((start, end)) => Ok(Rc::new(MalVal::String(s[start..end].to_string()))),
+        None => Err(MalError::raise(
+            "index-out-of-bounds",
+            format!("subs: range {start}..{end} out of bounds"),
+        )),
+    }
+}
+
+fn split(args: &[Rc<MalVal>]) -> MalResult {
+    let s = to_str("str/split", &args[0])?;
+    let limit = match args.get(2) {
+        Some(limit) => to_count("str/split", limit)?,
+        None => 0,
+    };
+    let matches = match args[1].as_ref() {
+        MalVal::String(separator) if separator.is_empty() => {
+            s.char_indices().map(|(i, _)| (i, i)).collect()
+        }
+        MalVal::String(separator) => s
+            .match_indices(separator.as_str())
+            .map(|(i, m)| (i, i + m.len()))
+            .collect(),
+        MalVal::Regex(re) => re.find_iter(s).map(|m| (m.start(), m.end())).collect(),
+        v => return Err(type_error("str/split", "string or regex", v)),
+    };
+    Ok(split_at(s, matches, limit))
+}
+
+fn split_at(s: &str, matches: Vec<(usize, usize)>, limit: i64) -> Rc<MalVal> {
+    let matches = matches
+        .into_iter()
+        .filter(|&(_, to)| to > 0)
+        .take(if limit > 0 {
+            limit as usize - 1
+        } else {
+            usize::MAX
+        })
+        .collect::<Vec<_>>();
+    if matches.is_empty() {
+        return Rc::new(MalVal::Vector(
+            vector![Rc::new(MalVal::String(s.to_string()))],
+            None,
+        ));
+    }
+    let mut parts = Vector::new();
+    let mut start = 0;
+    for (from, to) in matches {
+        parts.push_back(Rc::new(MalVal::String(s[start..from].to_string())));
+        start = to;
+    }
+    parts.push_back(Rc::new(MalVal::String(s[start..].to_string())));
+    if limit <= 0 {
+        while matches!(parts.back().map(|v| v.as_ref()), Some(MalVal::String(s)) if s.is_empty()) {
+            parts.pop_back();
+        }
+    }
+    Rc::new(MalVal::Vector(parts, None))
+}
+
+fn join(args: &[Rc<MalVal>]) -> MalResult {
+    let (separator, coll) = match args {
+        [coll] => ("", coll),
+        [separator, coll, ..] => (to_str("str/join", separator)?, coll),
+        [] => return Err(Arity::error("str/join", args.len())),
+    };
+    let mut parts = Vec::new();
+    for v in items("str/join", coll)? {
+        let v = v?;
+        v.realize()?;
+        parts.push(v.pr_str(false));
+    }
+    Ok(Rc::new(MalVal::String(parts.join(separator))))
+}
+
+fn map_str(name: &str, args: &[Rc<MalVal>], f: fn(&str) -> String) -> MalResult {
+    Ok(Rc::new(MalVal::String(f(to_str(name, &args[0])?))))
+}
+
+fn trim(args: &[Rc<MalVal>]) -> MalResult {
+    map_str("str/trim", args, |s| s.trim().to_string())
+}
+
+fn upper_case(args: &[Rc<MalVal>]) -> MalResult {
+    map_str("str/upper-case", args, str::to_uppercase)
+}
+
+fn lower_case(args: &[Rc<MalVal>]) -> MalResult {
+    map_str("str/lower-case", args, str::to_lowercase)
+}
+
+fn replace(args: &[Rc<MalVal>]) -> MalResult {
+    let s = to_str("str/replace", &args[0])?;
+    match (args[1].as_ref(), args[2].as_ref()) {
+        (MalVal::String(from), MalVal::String(to)) => {
+            Ok(Rc::new(MalVal::String(s.replace(from.as_str(), to))))
+        }
+        (MalVal::Char(from), MalVal::Char(to)) => Ok(Rc::new(MalVal::String(
+            s.chars()
+                .map(|c| if c == *from { *to } else { c })
+                .collect(),
+        ))),
+        (MalVal::Regex(re), MalVal::String(to)) => Ok(Rc::new(MalVal::String(
+            re.replace_all(s, to.as_str()).into_owned(),
+        ))),
+        (MalVal::Regex(re), MalVal::Fn(f, _)) => {
+            let mut buffer = String::new();
+            let mut last = 0;
+            for captures in re.captures_iter(s) {
+                let m = captures.get(0).unwrap();
+                buffer.push_str(&s[last..m.start()]);
+                buffer.push_str(&f.run(&[match_value(&captures)])?.pr_str(false));
+                last = m.end();
+            }
+            buffer.push_str(&s[last..]);
+            Ok(Rc::new(MalVal::String(buffer)))
+        }
+        (MalVal::String(_), v) => Err(type_error("str/replace", "string", v)),
+        (MalVal::Char(_), v) => Err(type_error("str/replace", "char", v)),
+        (MalVal::Regex(_), v) => Err(type_error("str/replace", "string or function", v)),
+        (v, _) => Err(type_error("str/replace", "string, char or regex", v)),
+    }
+}
+
+fn string_test(name: &str, args: &[Rc<MalVal>], test: fn(&str, &str) -> bool) -> MalResult {
+    let s = to_str(name, &args[0])?;
+    let part = to_str(name, &args[1])?;
+    Ok(Rc::new(MalVal::Bool(test(s, part))))
+}
+
+fn is_starts_with(args: &[Rc<MalVal>]) -> MalResult {
+    string_test("str/starts-with?", args, |s, part| s.starts_with(part))
+}
+
+fn is_ends_with(args: &[Rc<MalVal>]) -> MalResult {
+    string_test("str/ends-with?", args, |s, part| s.ends_with(part))
+}
+
+fn is_includes(args: &[Rc<MalVal>]) -> MalResult {
+    string_test("str/includes?", args, |s, part| s.contains(part))
+}
+
+fn index_of(args: &[Rc<MalVal>]) -> MalResult {
+    let s = to_str("str/index-of", &args[0])?;
+    let from = match args.get(2) {
+        Some(from) => to_count("str/index-of", from)?.max(0) as usize,
+        None => 0,
+    };
+    let Some(offset) = char_offset(s, from) else {
+        return Ok(Rc::new(MalVal::Nil));
+    };
+    let found = match args[1].as_ref() {
+        MalVal::String(part) => s[offset..].find(part.as_str()),
+        MalVal::Char(c) => s[offset..].find(*c),
+        v => return Err(type_error("str/index-of", "string or char", v)),
+    };
+    Ok(Rc::new(match found {
+        Some(i) => MalVal::Integer((from + s[offset..offset + i].chars().count()) as i64),
+        None => MalVal::Nil,
+    }))
+}
+
+fn is_blank(args: &[Rc<MalVal>]) -> MalResult {
+    match args[0].as_ref() {
+        MalVal::Nil => Ok(Rc::new(MalVal::Bool(true))),
+        MalVal::String(s) => Ok(Rc::new(MalVal::Bool(s.trim().is_empty()))),
+        v => Err(type_error("str/blank?", "string or nil", v)),
+    }
+}
+
+fn format(args: &[Rc<MalVal>]) -> MalResult {
+    realize_all(args)?;
+    let mut chars = to_str("format", &args[0])?.chars().peekable();
+    let mut values = args[1..].iter();
+    let mut out = String::new();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        let mut flags = String::new();
+        while let Some(flag) = chars.next_if(|c| "-+0".contains(*c)) {
+            flags.push(flag);
+        }
+        let width = format_digits(&mut chars);
+        let precision = chars
+            .next_if_eq(&'.')
+            .map(|_| format_digits(&mut chars).unwrap_or(0));
+        let conversion = chars.next().ok_or_else(|| {
+            MalError::raise(
+                "illegal-argument",
+                "format: incomplete format specifier".to_string(),
+            )
+        })?;
+        let text = match conversion {
+            '%' => "%".to_string(),
+            'n' => "\n".to_string(),
+            _ => {
+                let v = values.next().ok_or_else(|| {
+                    MalError::raise(
+                        "illegal-argument",
+                        format!("format: missing argument for %{conversion}"),
+                    )
+                })?;
+                format_value(conversion, precision, flags.contains('+'), v)?
+            }
+        };
+        let padding = width.unwrap_or(0).saturating_sub(text.chars().count());
+        if flags.contains('-') {
+            out.push_str(&text);
+            out.push_str(&" ".repeat(padding));
+        } else if flags.contains('0') && "dfexXo".contains(conversion) {
+            let digits = text.trim_start_matches(['-', '+']);
+            out.push_str(&text[..text.len() - digits.len()]);
+            out.push_str(&"0".repeat(padding));
+            out.push_str(digits);
+        } else {
+            out.push_str(&" ".repeat(padding));
+            out.push_str(&text);
+        }
+    }
+    Ok(Rc::new(MalVal::String(out)))
+}
+
+fn format_digits(chars: &mut std::iter::Peekable<std::str::Chars>) -> Option<usize> {
+    let mut digits = String::new();
+    while let Some(c) = chars.next_if(char::is_ascii_digit) {
+        digits.push(c);
+    }
+    digits.parse().ok()
+}
+
+fn format_value(
+    conversion: char,
+    precision: Option<usize>,
+    plus: bool,
+    v: &MalVal,
+) -> Result<String, MalError> {
+    let signed = |text: String| {
+        if plus && !text.starts_with('-') {
+            format!("+{text}")
+        } else {
+            text
+        }
+    };
+    match (conversion, v) {
+        ('s', v) => {
+            let text = v.pr_str(false);
+            Ok(match precision {
+                Some(p) => text.chars().take(p).collect(),
+                None => text,
+            })
+        }
+        ('d', MalVal::Integer(i)) => Ok(signed(i.to_string())),
+        ('d', MalVal::BigInt(i)) => Ok(signed(i.to_string())),
+        ('x', MalVal::Integer(_) | MalVal::BigInt(_)) => {
+            Ok(to_bigint("format", v)?.to_str_radix(16))
+        }
+        ('X', MalVal::Integer(_) | MalVal::BigInt(_)) => {
+            Ok(to_bigint("format", v)?.to_str_radix(16).to_uppercase())
         }
-        _ => unreachable!(),
+        ('o', MalVal::Integer(_) | MalVal::BigInt(_)) => {
+            Ok(to_bigint("format", v)?.to_str_radix(8))
+        }
+        ('f', v) if v.is_number() => Ok(signed(format!(
+            "{:.*}",
+            precision.unwrap_or(6),
+            to_float("format", v)?
+        ))),
+        ('e', v) if v.is_number() => {
+            let text = format!("{:.*e}", precision.unwrap_or(6), to_float("format", v)?);
+            let (mantissa, exponent) = text.split_once('e').unwrap_or((&text, "0"));
+            let exponent = exponent.parse::<i32>().unwrap_or(0);
+            Ok(signed(format!("{mantissa}e{exponent:+03}")))
+        }
+        ('c', MalVal::Char(c)) => Ok(c.to_string()),
+        ('d' | 'x' | 'X' | 'o', v) => Err(type_error("format", "integer", v)),
+        ('f' | 'e', v) => Err(type_error("format", "number", v)),
+        ('c', v) => Err(type_error("format", "char", v)),
+        (c, _) => Err(MalError::raise(
+            "illegal-argument",
+            format!("format: unknown conversion %{c}"),
+        )),
+    }
+}
+
+fn to_regex<'a>(name: &str, v: &'a MalVal) -> Result<&'a MalRegex, MalError> {
+    match v {
+        MalVal::Regex(re) => Ok(re),
+        v => Err(type_error(name, "regex", v)),
+    }
+}
+
+fn match_value(captures: &Captures) -> Rc<MalVal> {
+    let group = |m: Option<regex::Match>| {
+        Rc::new(m.map_or(MalVal::Nil, |m| MalVal::String(m.as_str().to_string())))
+    };
+    if captures.len() == 1 {
+        return group(captures.get(0));
+    }
+    Rc::new(MalVal::Vector(captures.iter().map(group).collect(), None))
+}
+
+fn re_pattern(args: &[Rc<MalVal>]) -> MalResult {
+    match args[0].as_ref() {
+        MalVal::Regex(_) => Ok(args[0].clone()),
+        MalVal::String(pattern) => compile_regex(pattern)
+            .map(|re| Rc::new(MalVal::Regex(re)))
+            .map_err(|e| MalError::raise("illegal-argument", format!("re-pattern: {e}"))),
+        v => Err(type_error("re-pattern", "string or regex", v)),
+    }
+}
+
+fn re_find(args: &[Rc<MalVal>]) -> MalResult {
+    let re = to_regex("re-find", &args[0])?;
+    let s = to_str("re-find", &args[1])?;
+    Ok(re
+        .captures(s)
+        .map_or_else(|| Rc::new(MalVal::Nil), |c| match_value(&c)))
+}
+
+fn re_matches(args: &[Rc<MalVal>]) -> MalResult {
+    let re = to_regex("re-matches", &args[0])?;
+    let s = to_str("re-matches", &args[1])?;
+    let anchored = re
+        .anchored()
+        .map_err(|e| MalError::raise("illegal-argument", format!("re-matches: {e}")))?;
+    Ok(anchored
+        .captures(s)
+        .map_or_else(|| Rc::new(MalVal::Nil), |c| match_value(&c)))
+}
+
+fn re_seq(args: &[Rc<MalVal>]) -> MalResult {
+    let re = to_regex("re-seq", &args[0])?;
+    let s = to_str("re-seq", &args[1])?;
+    let matches = re
+        .captures_iter(s)
+        .map(|c| match_value(&c))
+        .collect::<Vector<_>>();
+    if matches.is_empty() {
+        return Ok(Rc::new(MalVal::Nil));
     }
+    Ok(Rc::new(MalVal::List(matches, None)))
 }
 
 fn is_string(args: &[Rc<MalVal>]) -> MalResult {
@@ -583,13 +2447,113 @@ fn is_string(args: &[Rc<MalVal>]) -> MalResult {
     }
 }
 
+fn is_char(args: &[Rc<MalVal>]) -> MalResult {
+    match args[0].as_ref() {
+        MalVal::Char(..) => Ok(Rc::new(MalVal::Bool(true))),
+        _ => Ok(Rc::new(MalVal::Bool(false))),
+    }
+}
+
+fn char(args: &[Rc<MalVal>]) -> MalResult {
+    match args[0].as_ref() {
+        MalVal::Char(_) => Ok(args[0].clone()),
+        MalVal::Integer(i) => u32::try_from(*i)
+            .ok()
+            .and_then(char::from_u32)
+            .map(|c| Rc::new(MalVal::Char(c)))
+            .ok_or_else(|| {
+                MalError::raise(
+                    "arithmetic-error",
+                    format!("Value out of range for char: {i}"),
+                )
+            }),
+        v => Err(type_error("char", "integer or char", v)),
+    }
+}
+
 fn is_number(args: &[Rc<MalVal>]) -> MalResult {
+    Ok(Rc::new(MalVal::Bool(args[0].is_number())))
+}
+
+fn is_float(args: &[Rc<MalVal>]) -> MalResult {
+    match args[0].as_ref() {
+        MalVal::Float(..) => Ok(Rc::new(MalVal::Bool(true))),
+        _ => Ok(Rc::new(MalVal::Bool(false))),
+    }
+}
+
+fn int(args: &[Rc<MalVal>]) -> MalResult {
+    match args[0].as_ref() {
+        MalVal::Integer(_) => Ok(args[0].clone()),
+        MalVal::BigInt(_) | MalVal::Ratio(_) => {
+            let i = to_ratio("int", &args[0])?.to_integer();
+            i.to_i64()
+                .map(|i| Rc::new(MalVal::Integer(i)))
+                .ok_or_else(|| {
+                    MalError::raise(
+                        "arithmetic-error",
+                        format!("Value out of range for int: {i}"),
+                    )
+                })
+        }
+        MalVal::Float(f) => Ok(Rc::new(MalVal::Integer(*f as i64))),
+        MalVal::Char(c) => Ok(Rc::new(MalVal::Integer(*c as i64))),
+        v => Err(type_error("int", "number or char", v)),
+    }
+}
+
+fn double(args: &[Rc<MalVal>]) -> MalResult {
+    match args[0].as_ref() {
+        MalVal::Integer(_) | MalVal::BigInt(_) | MalVal::Ratio(_) => {
+            Ok(Rc::new(MalVal::Float(to_float("double", &args[0])?)))
+        }
+        MalVal::Float(_) => Ok(args[0].clone()),
+        v => Err(type_error("double", "number", v)),
+    }
+}
+
+fn is_ratio(args: &[Rc<MalVal>]) -> MalResult {
     match args[0].as_ref() {
-        MalVal::Integer(..) => Ok(Rc::new(MalVal::Bool(true))),
+        MalVal::Ratio(..) => Ok(Rc::new(MalVal::Bool(true))),
         _ => Ok(Rc::new(MalVal::Bool(false))),
     }
 }
 
+fn numerator(args: &[Rc<MalVal>]) -> MalResult {
+    match args[0].as_ref() {
+        MalVal::Integer(_) | MalVal::BigInt(_) => Ok(args[0].clone()),
+        MalVal::Ratio(r) => Ok(Rc::new(BigRational::from_integer(r.numer().clone()).into())),
+        v => Err(type_error("numerator", "integer or ratio", v)),
+    }
+}
+
+fn denominator(args: &[Rc<MalVal>]) -> MalResult {
+    match args[0].as_ref() {
+        MalVal::Integer(_) | MalVal::BigInt(_) => Ok(Rc::new(MalVal::Integer(1))),
+        MalVal::Ratio(r) => Ok(Rc::new(BigRational::from_integer(r.denom().clone()).into())),
+        v => Err(type_error("denominator", "integer or ratio", v)),
+    }
+}
+
+fn rationalize(args: &[Rc<MalVal>]) -> MalResult {
+    match args[0].as_ref() {
+        MalVal::Integer(_) | MalVal::BigInt(_) | MalVal::Ratio(_) => Ok(args[0].clone()),
+        MalVal::Float(f) if f.is_finite() => {
+            // use the shortest decimal form so that 0.1 becomes 1/10
+            let digits = format!("{f}");
+            let (int, frac) = digits.split_once('.').unwrap_or((&digits, ""));
+            let numer = format!("{int}{frac}").parse::<BigInt>().unwrap();
+            let denom = BigInt::from(10).pow(frac.len());
+            Ok(Rc::new(BigRational::new(numer, denom).into()))
+        }
+        MalVal::Float(f) => Err(MalError::raise(
+            "arithmetic-error",
+            format!("Cannot rationalize {}", MalVal::Float(*f)),
+        )),
+        v => Err(type_error("rationalize", "number", v)),
+    }
+}
+
 fn is_fn(args: &[Rc<MalVal>]) -> MalResult {
     match args[0].as_ref() {
         MalVal::Fn(f, _) => match f.as_ref() {
@@ -623,7 +2587,7 @@ fn seq(args: &[Rc<MalVal>]) -> MalResult {
             if vector.is_empty() {
                 Ok(Rc::new(MalVal::Nil))
             } else {
-                Ok(Rc::new(MalVal::List(vector.to_vec(), data.clone())))
+                Ok(Rc::new(MalVal::List(vector.clone(), data.clone())))
             }
         }
         MalVal::String(string) => {
@@ -631,15 +2595,38 @@ fn seq(args: &[Rc<MalVal>]) -> MalResult {
                 Ok(Rc::new(MalVal::Nil))
             } else {
                 Ok(Rc::new(MalVal::List(
-                    string
-                        .chars()
-                        .map(|c| Rc::new(MalVal::String(c.to_string())))
-                        .collect(),
+                    string.chars().map(|c| Rc::new(MalVal::Char(c))).collect(),
+                    None,
+                )))
+            }
+        }
+        MalVal::Set(set, _) => {
+            if set.is_empty() {
+                Ok(Rc::new(MalVal::Nil))
+            } else {
+                Ok(Rc::new(MalVal::List(
+                    set.iter().map(|k| k.into()).collect(),
                     None,
                 )))
             }
         }
+        MalVal::HashMap(..) | MalVal::SortedMap(..) => {
+            let entries = items("seq", &args[0])?.collect::<Result<Vector<_>, _>>()?;
+            if entries.is_empty() {
+                Ok(Rc::new(MalVal::Nil))
+            } else {
+                Ok(Rc::new(MalVal::List(entries, None)))
+            }
+        }
+        MalVal::LazySeq(seq, _) => match seq.step()? {
+            Some(_) => Ok(args[0].clone()),
+            None => Ok(Rc::new(MalVal::Nil)),
+        },
         MalVal::Nil => Ok(args[0].clone()),
-        _ => unreachable!(),
+        v => Err(type_error(
+            "seq",
+            "list, vector, map, set, lazy-seq, string or nil",
+            v,
+        )),
     }
 }
@@ -1,48 +1,123 @@
-use std::{collections::HashMap, iter::Peekable, rc::Rc, str::CharIndices};
+use std::{iter::Peekable, rc::Rc, str::CharIndices};
 
-use crate::{types::MalVal, MalError};
+use im_rc::{vector, HashMap, HashSet, Vector};
+use num_bigint::BigInt;
+use num_rational::BigRational;
+
+use crate::{
+    types::{compile_regex, Hashable, MalVal, CHAR_NAMES},
+    MalError, ReadError,
+};
 
 struct Reader<'a> {
     source: &'a str,
     iter: Peekable<CharIndices<'a>>,
+    peeked: Option<Option<(usize, &'a str)>>,
+    lines: Vec<usize>,
+    file: Option<Rc<MalVal>>,
 }
 
 impl<'a> Reader<'a> {
-    fn new(source: &'a str) -> Self {
+    fn new(source: &'a str, file: Option<&str>) -> Self {
         Self {
             source,
             iter: source.char_indices().peekable(),
+            peeked: None,
+            lines: source.match_indices('\n').map(|(i, _)| i).collect(),
+            file: file.map(|f| Rc::new(MalVal::String(f.to_string()))),
         }
     }
-}
 
-impl<'a> Iterator for Reader<'a> {
-    type Item = &'a str;
+    fn peek(&mut self) -> Option<(usize, &'a str)> {
+        if self.peeked.is_none() {
+            self.peeked = Some(self.token());
+        }
+        self.peeked.flatten()
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        while self
-            .iter
-            .next_if(|(_, c)| c.is_whitespace() || c == &',')
-            .is_some()
-        {}
+    fn line_column(&self, offset: usize) -> (usize, usize) {
+        let line = self.lines.partition_point(|&i| i < offset);
+        let line_start = match line {
+            0 => 0,
+            _ => self.lines[line - 1] + 1,
+        };
+        (
+            line + 1,
+            self.source[line_start..offset].chars().count() + 1,
+        )
+    }
+
+    fn location(&self, offset: usize) -> Rc<MalVal> {
+        let (line, column) = self.line_column(offset);
+        let mut position = HashMap::from(vec![
+            (
+                Hashable::keyword("line"),
+                Rc::new(MalVal::Integer(line as i64)),
+            ),
+            (
+                Hashable::keyword("column"),
+                Rc::new(MalVal::Integer(column as i64)),
+            ),
+        ]);
+        if let Some(file) = &self.file {
+            position.insert(Hashable::keyword("file"), file.clone());
+        }
+        Rc::new(MalVal::HashMap(position, None))
+    }
+
+    fn error(&self, offset: usize, message: String) -> MalError {
+        let (line, column) = self.line_column(offset);
+        let file = match self.file.as_deref() {
+            Some(MalVal::String(file)) => Some(file.clone()),
+            _ => None,
+        };
+        MalError::Read(ReadError {
+            message: format!("{message} at {line}:{column}"),
+            file,
+            line,
+            column,
+        })
+    }
+
+    fn skip_string(&mut self) {
+        while let Some((_, c)) = self.iter.next() {
+            if c == '\\' {
+                self.iter.next();
+            } else if c == '"' {
+                break;
+            }
+        }
+    }
+
+    fn token(&mut self) -> Option<(usize, &'a str)> {
+        loop {
+            while self
+                .iter
+                .next_if(|(_, c)| c.is_whitespace() || c == &',')
+                .is_some()
+            {}
+            if self.iter.next_if(|(_, c)| c == &';').is_none() {
+                break;
+            }
+            self.iter.any(|(_, c)| c == '\n');
+        }
         if let Some((i, c)) = self.iter.next() {
             match c {
                 '~' => {
                     self.iter.next_if(|(_, c)| c == &'@');
                 }
+                '#' if self.iter.next_if(|(_, c)| c == &'{').is_some() => (),
+                '#' if self.iter.next_if(|(_, c)| c == &'"').is_some() => self.skip_string(),
                 _ if "[]{}()'`~^@".contains(c) => (),
-                '"' => {
-                    while let Some((_, c)) = self.iter.next() {
-                        if c == '\\' {
-                            self.iter.next();
-                        } else if c == '"' {
-                            break;
-                        }
-                    }
-                }
-                ';' => {
-                    self.iter.any(|(_, c)| c == '\n');
+                '\\' => {
+                    self.iter.next();
+                    while self
+                        .iter
+                        .next_if(|(_, c)| !c.is_whitespace() && !"[]{}()'`~^@\";,".contains(*c))
+                        .is_some()
+                    {}
                 }
+                '"' => self.skip_string(),
                 _ if !"[]{}()'`~^@\";".contains(c) => {
                     while self
                         .iter
@@ -53,8 +128,8 @@ impl<'a> Iterator for Reader<'a> {
                 _ => unreachable!(),
             }
             match self.iter.peek() {
-                Some((j, _)) => Some(&self.source[i..*j]),
-                None => Some(&self.source[i..]),
+                Some((j, _)) => Some((i, &self.source[i..*j])),
+                None => Some((i, &self.source[i..])),
             }
         } else {
             None
@@ -62,41 +137,77 @@ impl<'a> Iterator for Reader<'a> {
     }
 }
 
+impl<'a> Iterator for Reader<'a> {
+    type Item = (usize, &'a str);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.peeked.take() {
+            Some(token) => token,
+            None => self.token(),
+        }
+    }
+}
+
 pub fn read_str(input: &str) -> Result<MalVal, MalError> {
-    let mut reader = Reader::new(input).peekable();
+    let mut reader = Reader::new(input, None);
     read_form(&mut reader)
 }
 
-fn read_form(reader: &mut Peekable<Reader>) -> Result<MalVal, MalError> {
+pub fn read_file(input: &str, file: &str) -> Result<MalVal, MalError> {
+    let mut reader = Reader::new(input, Some(file));
+    let mut forms = vector![Rc::new(MalVal::Symbol("do".to_string()))];
+    while reader.peek().is_some() {
+        match read_form(&mut reader) {
+            Ok(form) => forms.push_back(Rc::new(form)),
+            Err(MalError::Continue) => break,
+            Err(e) => return Err(e),
+        }
+    }
+    forms.push_back(Rc::new(MalVal::Nil));
+    Ok(MalVal::List(forms, Some(reader.location(0))))
+}
+
+fn read_form(reader: &mut Reader) -> Result<MalVal, MalError> {
     match reader.next() {
-        Some("(") => read_list(reader),
-        Some("[") => read_vector(reader),
-        Some("{") => read_hashmap(reader),
-        Some("@") => Ok(MalVal::List(
-            vec![
-                Rc::new(MalVal::Symbol("deref".to_string())),
-                Rc::new(read_form(reader)?),
-            ],
-            None,
-        )),
+        Some((i, "(")) => read_list(reader, i),
+        Some((i, "[")) => read_vector(reader, i),
+        Some((i, "{")) => read_hashmap(reader, i),
+        Some((i, "#{")) => read_set(reader, i),
+        Some((i, s @ (")" | "]" | "}"))) => Err(reader.error(i, format!("unexpected '{s}'"))),
         None => Err(MalError::Continue),
-        Some(s) => {
+        Some((i, s)) => {
             let mut iter = s.chars().peekable();
             iter.next_if_eq(&'-');
             if let Some(n) = iter.next() {
-                if n.is_ascii_digit() {
+                if n.is_ascii_digit() || (n == '.' && iter.next_if(char::is_ascii_digit).is_some())
+                {
                     if let Ok(i) = s.parse::<i64>() {
                         return Ok(MalVal::Integer(i));
                     }
+                    if let Ok(i) = s.strip_suffix('N').unwrap_or(s).parse::<BigInt>() {
+                        return Ok(MalVal::BigInt(i));
+                    }
+                    if let Ok(f) = s.parse::<f64>() {
+                        return Ok(MalVal::Float(f));
+                    }
+                    if let Some((n, d)) = s.split_once('/') {
+                        if let (Ok(n), Ok(d)) = (n.parse::<BigInt>(), d.parse::<BigInt>()) {
+                            if d != BigInt::from(0) {
+                                return Ok(BigRational::new(n, d).into());
+                            }
+                        }
+                    }
                 }
             }
             let first = s.chars().next().unwrap();
             if first == ':' {
                 Ok(MalVal::Keyword(s[1..].to_string()))
+            } else if s.starts_with("#\"") {
+                read_regex(reader, s, i)
             } else if first == '"' {
-                Ok(MalVal::String(unescape(&s[1..])?))
-            } else if first == ';' {
-                read_form(reader)
+                Ok(MalVal::String(unescape(reader, s, i)?))
+            } else if first == '\\' {
+                read_char(reader, s, i)
             } else {
                 match s {
                     "'" | "`" | "~" | "~@" | "@" => {
@@ -108,27 +219,30 @@ fn read_form(reader: &mut Peekable<Reader>) -> Result<MalVal, MalError> {
                             "@" => "deref",
                             _ => unreachable!(),
                         };
-                        let v = read_form(reader)?;
+                        let v = read_operand(reader, i, s)?;
                         Ok(MalVal::List(
-                            vec![Rc::new(MalVal::Symbol(prefix.to_string())), Rc::new(v)],
-                            None,
+                            vector![Rc::new(MalVal::Symbol(prefix.to_string())), Rc::new(v)],
+                            Some(reader.location(i)),
                         ))
                     }
                     "^" => {
-                        let first = read_form(reader)?;
-                        let second = read_form(reader)?;
+                        let first = read_operand(reader, i, s)?;
+                        let second = read_operand(reader, i, s)?;
                         Ok(MalVal::List(
-                            vec![
+                            vector![
                                 Rc::new(MalVal::Symbol("with-meta".to_string())),
                                 Rc::new(second),
                                 Rc::new(first),
                             ],
-                            None,
+                            Some(reader.location(i)),
                         ))
                     }
                     "true" => Ok(MalVal::Bool(true)),
                     "false" => Ok(MalVal::Bool(false)),
                     "nil" => Ok(MalVal::Nil),
+                    "##NaN" => Ok(MalVal::Float(f64::NAN)),
+                    "##Inf" => Ok(MalVal::Float(f64::INFINITY)),
+                    "##-Inf" => Ok(MalVal::Float(f64::NEG_INFINITY)),
                     _ => Ok(MalVal::Symbol(s.to_string())),
                 }
             }
@@ -136,65 +250,179 @@ fn read_form(reader: &mut Peekable<Reader>) -> Result<MalVal, MalError> {
     }
 }
 
-fn read_list(reader: &mut Peekable<Reader>) -> Result<MalVal, MalError> {
-    let mut list = Vec::new();
-    while let Some(&s) = reader.peek() {
+fn read_operand(reader: &mut Reader, start: usize, prefix: &str) -> Result<MalVal, MalError> {
+    match read_form(reader) {
+        Err(MalError::Continue) => {
+            Err(reader.error(start, format!("expected a form after '{prefix}'")))
+        }
+        v => v,
+    }
+}
+
+fn unbalanced(reader: &Reader, start: usize, opener: &str) -> MalError {
+    reader.error(start, format!("unbalanced '{opener}' opened"))
+}
+
+fn read_list(reader: &mut Reader, start: usize) -> Result<MalVal, MalError> {
+    let mut list = Vector::new();
+    while let Some((_, s)) = reader.peek() {
         match s {
             ")" => {
                 reader.next();
-                return Ok(MalVal::List(list, None));
+                return Ok(MalVal::List(list, Some(reader.location(start))));
             }
-            _ => list.push(Rc::new(read_form(reader)?)),
+            "]" | "}" => return Err(unbalanced(reader, start, "(")),
+            _ => list.push_back(Rc::new(read_form(reader)?)),
         }
     }
-    for i in list {
-        println!("{} ", i.as_ref());
-    }
-    Err(MalError::Unbalance("list"))
+    Err(unbalanced(reader, start, "("))
 }
 
-fn read_vector(reader: &mut Peekable<Reader>) -> Result<MalVal, MalError> {
-    let mut vector = Vec::new();
-    while let Some(&s) = reader.peek() {
+fn read_vector(reader: &mut Reader, start: usize) -> Result<MalVal, MalError> {
+    let mut vector = Vector::new();
+    while let Some((_, s)) = reader.peek() {
         match s {
             "]" => {
                 reader.next();
-                return Ok(MalVal::Vector(vector, None));
+                return Ok(MalVal::Vector(vector, Some(reader.location(start))));
             }
-            _ => vector.push(Rc::new(read_form(reader)?)),
+            ")" | "}" => return Err(unbalanced(reader, start, "[")),
+            _ => vector.push_back(Rc::new(read_form(reader)?)),
         }
     }
-    Err(MalError::Unbalance("vector"))
+    Err(unbalanced(reader, start, "["))
 }
 
-fn read_hashmap(reader: &mut Peekable<Reader>) -> Result<MalVal, MalError> {
+fn read_hashmap(reader: &mut Reader, start: usize) -> Result<MalVal, MalError> {
     let mut hashmap = HashMap::new();
-    while let Some(&s) = reader.peek() {
+    while let Some((_, s)) = reader.peek() {
         match s {
             "}" => {
                 reader.next();
-                return Ok(MalVal::HashMap(hashmap, None));
+                return Ok(MalVal::HashMap(hashmap, Some(reader.location(start))));
             }
+            ")" | "]" => return Err(unbalanced(reader, start, "{")),
             _ => {
-                let k = (&read_form(reader)?).into();
+                let k = Hashable::try_from(Rc::new(read_form(reader)?))?;
+                match reader.peek() {
+                    Some((_, "}")) => {
+                        return Err(reader.error(
+                            start,
+                            "map literal must contain an even number of forms".to_string(),
+                        ))
+                    }
+                    Some((_, ")" | "]")) => return Err(unbalanced(reader, start, "{")),
+                    _ => (),
+                }
                 let v = read_form(reader)?;
                 hashmap.insert(k, Rc::new(v));
             }
         }
     }
-    Err(MalError::Unbalance("hash-map"))
+    Err(unbalanced(reader, start, "{"))
+}
+
+fn read_char(reader: &Reader, s: &str, start: usize) -> Result<MalVal, MalError> {
+    let name = &s[1..];
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Ok(MalVal::Char(c));
+    }
+    if let Some((_, c)) = CHAR_NAMES.iter().find(|(n, _)| *n == name) {
+        return Ok(MalVal::Char(*c));
+    }
+    let mut iter = name.char_indices();
+    if let (Some((_, 'u')), 5) = (iter.next(), name.len()) {
+        if let Some(c) = hex4(&mut iter).and_then(char::from_u32) {
+            return Ok(MalVal::Char(c));
+        }
+    }
+    Err(reader.error(start, format!("invalid character literal {s}")))
+}
+
+fn read_regex(reader: &Reader, s: &str, start: usize) -> Result<MalVal, MalError> {
+    let mut pattern = String::new();
+    let mut chars = s[2..].chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('"')) => {
+                pattern.push('"');
+                chars.next();
+            }
+            ('\\', Some(escaped)) => {
+                pattern.push(c);
+                pattern.push(escaped);
+                chars.next();
+            }
+            ('"', _) => {
+                return compile_regex(&pattern)
+                    .map(MalVal::Regex)
+                    .map_err(|e| reader.error(start, format!("invalid regex: {e}")));
+            }
+            _ => pattern.push(c),
+        }
+    }
+    Err(unbalanced(reader, start, "#\""))
 }
 
-fn unescape(s: &str) -> Result<String, MalError> {
+fn read_set(reader: &mut Reader, start: usize) -> Result<MalVal, MalError> {
+    let mut set = HashSet::new();
+    while let Some((i, s)) = reader.peek() {
+        match s {
+            "}" => {
+                reader.next();
+                return Ok(MalVal::Set(set, Some(reader.location(start))));
+            }
+            ")" | "]" => return Err(unbalanced(reader, start, "#{")),
+            _ => {
+                let v = Rc::new(read_form(reader)?);
+                if set.insert(Hashable::try_from(v.clone())?).is_some() {
+                    return Err(reader.error(i, format!("duplicate set element {v}")));
+                }
+            }
+        }
+    }
+    Err(unbalanced(reader, start, "#{"))
+}
+
+fn unescape(reader: &Reader, s: &str, start: usize) -> Result<String, MalError> {
     let mut buffer = String::with_capacity(s.len());
-    let mut iter = s.chars().peekable();
-    while let Some(c) = iter.next() {
+    let mut iter = s.char_indices().skip(1);
+    while let Some((i, c)) = iter.next() {
         if c == '\\' {
             match iter.next() {
-                Some('\\') => buffer.push('\\'),
-                Some('n') => buffer.push('\n'),
-                Some('"') => buffer.push('"'),
-                _ => unreachable!(),
+                Some((_, '\\')) => buffer.push('\\'),
+                Some((_, 'n')) => buffer.push('\n'),
+                Some((_, '"')) => buffer.push('"'),
+                Some((_, 't')) => buffer.push('\t'),
+                Some((_, 'r')) => buffer.push('\r'),
+                Some((_, '0')) => buffer.push('\0'),
+                Some((_, 'u')) => {
+                    let mut code = hex4(&mut iter);
+                    if let Some(high @ 0xd800..=0xdbff) = code {
+                        let mut lookahead = iter.clone();
+                        if let (Some((_, '\\')), Some((_, 'u'))) =
+                            (lookahead.next(), lookahead.next())
+                        {
+                            if let Some(low @ 0xdc00..=0xdfff) = hex4(&mut lookahead) {
+                                code = Some(0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00));
+                                iter = lookahead;
+                            }
+                        }
+                    }
+                    match code.and_then(char::from_u32) {
+                        Some(c) => buffer.push(c),
+                        None => {
+                            return Err(
+                                reader.error(start + i, "invalid unicode escape".to_string())
+                            )
+                        }
+                    }
+                }
+                Some((_, c)) => {
+                    return Err(reader.error(start + i, format!("invalid escape \\{c}")));
+                }
+                None => break,
             }
         } else if c == '"' {
             return Ok(buffer);
@@ -202,5 +430,13 @@ fn unescape(s: &str) -> Result<String, MalError> {
             buffer.push(c);
         }
     }
-    Err(MalError::Unbalance("string"))
+    Err(unbalanced(reader, start, "\""))
+}
+
+fn hex4(iter: &mut impl Iterator<Item = (usize, char)>) -> Option<u32> {
+    let mut code = 0;
+    for _ in 0..4 {
+        code = code * 16 + iter.next()?.1.to_digit(16)?;
+    }
+    Some(code)
 }
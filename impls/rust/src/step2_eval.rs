@@ -1,6 +1,8 @@
-use std::{collections::HashMap, io::Write, rc::Rc};
+use std::{io::Write, rc::Rc};
 
-use rust2::{read_str, MalError, MalFn, MalResult, MalVal};
+use im_rc::{HashMap, Vector};
+
+use rust2::{read_str, Arity, MalError, MalFn, MalResult, MalVal};
 
 fn read(input: &str) -> Result<MalVal, MalError> {
     read_str(input)
@@ -15,16 +17,16 @@ fn eval_ast(ast: Rc<MalVal>, env: &HashMap<String, Rc<MalFn>>) -> MalResult {
             None,
         ))),
         MalVal::List(list, _) => {
-            let mut buffer = Vec::new();
+            let mut buffer = Vector::new();
             for v in list {
-                buffer.push(eval(v.clone(), env)?);
+                buffer.push_back(eval(v.clone(), env)?);
             }
             Ok(Rc::new(MalVal::List(buffer, None)))
         }
         MalVal::Vector(vector, _) => {
-            let mut buffer = Vec::new();
+            let mut buffer = Vector::new();
             for v in vector {
-                buffer.push(eval(v.clone(), env)?);
+                buffer.push_back(eval(v.clone(), env)?);
             }
             Ok(Rc::new(MalVal::Vector(buffer, None)))
         }
@@ -47,7 +49,9 @@ fn eval(ast: Rc<MalVal>, env: &HashMap<String, Rc<MalFn>>) -> MalResult {
             match ast.as_ref() {
                 MalVal::List(list, _) => match list[0].as_ref() {
                     MalVal::Fn(func, _) => match func.as_ref() {
-                        MalFn::RegularFn(func) => (func)(&list[1..]),
+                        MalFn::RegularFn(func) => {
+                            func.run(&list.iter().skip(1).cloned().collect::<Vec<_>>())
+                        }
                         _ => unreachable!(),
                     },
                     _ => unreachable!(),
@@ -100,10 +104,22 @@ fn div(args: &[Rc<MalVal>]) -> MalResult {
 
 fn main() {
     let mut env: HashMap<String, Rc<MalFn>> = HashMap::new();
-    env.insert("+".to_string(), Rc::new(MalFn::RegularFn(Rc::new(add))));
-    env.insert("-".to_string(), Rc::new(MalFn::RegularFn(Rc::new(sub))));
-    env.insert("*".to_string(), Rc::new(MalFn::RegularFn(Rc::new(mul))));
-    env.insert("/".to_string(), Rc::new(MalFn::RegularFn(Rc::new(div))));
+    env.insert(
+        "+".to_string(),
+        Rc::new(MalFn::regular_fn("+", Arity::exact(2), add)),
+    );
+    env.insert(
+        "-".to_string(),
+        Rc::new(MalFn::regular_fn("-", Arity::exact(2), sub)),
+    );
+    env.insert(
+        "*".to_string(),
+        Rc::new(MalFn::regular_fn("*", Arity::exact(2), mul)),
+    );
+    env.insert(
+        "/".to_string(),
+        Rc::new(MalFn::regular_fn("/", Arity::exact(2), div)),
+    );
 
     let mut buffer = String::new();
     loop {
@@ -1,6 +1,8 @@
-use std::{cell::RefCell, collections::HashMap, io::Write, rc::Rc};
+use std::{cell::RefCell, io::Write, rc::Rc};
 
-use rust2::{read_str, Env, MalError, MalFn, MalResult, MalVal};
+use im_rc::{HashMap, Vector};
+
+use rust2::{read_str, Arity, Env, MalError, MalFn, MalResult, MalVal};
 
 fn read(input: &str) -> Result<MalVal, MalError> {
     read_str(input)
@@ -13,16 +15,16 @@ fn eval_ast(ast: Rc<MalVal>, env: Rc<RefCell<Env>>) -> MalResult {
             .get(symbol)
             .ok_or_else(|| MalError::Other(format!("'{symbol}' not found."))),
         MalVal::List(list, _) => {
-            let mut buffer = Vec::new();
+            let mut buffer = Vector::new();
             for v in list {
-                buffer.push(eval(v.clone(), env.clone())?);
+                buffer.push_back(eval(v.clone(), env.clone())?);
             }
             Ok(Rc::new(MalVal::List(buffer, None)))
         }
         MalVal::Vector(vector, _) => {
-            let mut buffer = Vec::new();
+            let mut buffer = Vector::new();
             for v in vector {
-                buffer.push(eval(v.clone(), env.clone())?);
+                buffer.push_back(eval(v.clone(), env.clone())?);
             }
             Ok(Rc::new(MalVal::Vector(buffer, None)))
         }
@@ -78,7 +80,9 @@ fn eval(ast: Rc<MalVal>, env: Rc<RefCell<Env>>) -> MalResult {
             match ast.as_ref() {
                 MalVal::List(list, _) => match list[0].as_ref() {
                     MalVal::Fn(func, _) => match func.as_ref() {
-                        MalFn::RegularFn(func) => (func)(&list[1..]),
+                        MalFn::RegularFn(func) => {
+                            func.run(&list.iter().skip(1).cloned().collect::<Vec<_>>())
+                        }
                         _ => unreachable!(),
                     },
                     _ => unreachable!(),
@@ -133,19 +137,31 @@ fn main() {
     let mut env = Env::default();
     env.set(
         "+".to_string(),
-        Rc::new(MalVal::Fn(Rc::new(MalFn::RegularFn(Rc::new(add))), None)),
+        Rc::new(MalVal::Fn(
+            Rc::new(MalFn::regular_fn("+", Arity::exact(2), add)),
+            None,
+        )),
     );
     env.set(
         "-".to_string(),
-        Rc::new(MalVal::Fn(Rc::new(MalFn::RegularFn(Rc::new(sub))), None)),
+        Rc::new(MalVal::Fn(
+            Rc::new(MalFn::regular_fn("-", Arity::exact(2), sub)),
+            None,
+        )),
     );
     env.set(
         "*".to_string(),
-        Rc::new(MalVal::Fn(Rc::new(MalFn::RegularFn(Rc::new(mul))), None)),
+        Rc::new(MalVal::Fn(
+            Rc::new(MalFn::regular_fn("*", Arity::exact(2), mul)),
+            None,
+        )),
     );
     env.set(
         "/".to_string(),
-        Rc::new(MalVal::Fn(Rc::new(MalFn::RegularFn(Rc::new(div))), None)),
+        Rc::new(MalVal::Fn(
+            Rc::new(MalFn::regular_fn("/", Arity::exact(2), div)),
+            None,
+        )),
     );
     let env = Rc::new(RefCell::new(env));
 
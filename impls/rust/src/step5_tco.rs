@@ -1,4 +1,6 @@
-use std::{cell::RefCell, collections::HashMap, io::Write, rc::Rc};
+use std::{cell::RefCell, io::Write, rc::Rc};
+
+use im_rc::{HashMap, Vector};
 
 use rust2::{read_str, Env, MalError, MalFn, MalResult, MalVal, NS};
 
@@ -14,16 +16,16 @@ fn eval_ast(ast: Rc<MalVal>, env: Rc<RefCell<Env>>) -> MalResult {
             .get(symbol)
             .ok_or_else(|| MalError::Other(format!("'{symbol}' not found."))),
         MalVal::List(list, _) => {
-            let mut buffer = Vec::new();
+            let mut buffer = Vector::new();
             for v in list {
-                buffer.push(eval(v.clone(), env.clone())?);
+                buffer.push_back(eval(v.clone(), env.clone())?);
             }
             Ok(Rc::new(MalVal::List(buffer, None)))
         }
         MalVal::Vector(vector, _) => {
-            let mut buffer = Vec::new();
+            let mut buffer = Vector::new();
             for v in vector {
-                buffer.push(eval(v.clone(), env.clone())?);
+                buffer.push_back(eval(v.clone(), env.clone())?);
             }
             Ok(Rc::new(MalVal::Vector(buffer, None)))
         }
@@ -49,7 +51,7 @@ fn eval(ast: Rc<MalVal>, env: Rc<RefCell<Env>>) -> MalResult {
                     match symbol.as_str() {
                         "def!" => match list[1].as_ref() {
                             MalVal::Symbol(symbol) => {
-                                let v = eval(list[2].clone(), env.clone())?;
+                                let v = eval(list[2].clone(), env.clone())?.with_name(symbol);
                                 env.borrow_mut().set(symbol.clone(), v.clone());
                                 return Ok(v);
                             }
@@ -81,7 +83,7 @@ fn eval(ast: Rc<MalVal>, env: Rc<RefCell<Env>>) -> MalResult {
                         }
                         "do" => {
                             let mut value = eval(list[1].clone(), env.clone())?;
-                            for v in &list[2..] {
+                            for v in list.iter().skip(2) {
                                 value = eval(v.clone(), env.clone())?;
                             }
                             return Ok(value);
@@ -124,10 +126,12 @@ fn eval(ast: Rc<MalVal>, env: Rc<RefCell<Env>>) -> MalResult {
                 match n_ast.as_ref() {
                     MalVal::List(list, _) => match list[0].as_ref() {
                         MalVal::Fn(func, _) => match func.as_ref() {
-                            MalFn::RegularFn(func) => return (func)(&list[1..]),
+                            MalFn::RegularFn(func) => {
+                                return func.run(&list.iter().skip(1).cloned().collect::<Vec<_>>())
+                            }
                             MalFn::MalFunc(func) => {
-                                let mut n_env = Env::new(func.env.clone());
-                                n_env.bind_expr(func.params.clone(), list[1..].to_vec());
+                                let n_env =
+                                    func.bind(&list.iter().skip(1).cloned().collect::<Vec<_>>())?;
                                 ast = func.ast.clone();
                                 env = Rc::new(RefCell::new(n_env));
                             }
@@ -148,7 +152,7 @@ fn print(val: &MalVal) -> String {
 
 fn rep(input: &str, env: &Rc<RefCell<Env>>) -> String {
     match read(input) {
-        Ok(ast) => match eval(Rc::new(ast), env.clone()) {
+        Ok(ast) => match eval(Rc::new(ast), env.clone()).and_then(|v| v.realize().map(|_| v)) {
             Ok(v) => print(v.as_ref()),
             Err(e) => e.to_string(),
         },
@@ -158,10 +162,10 @@ fn rep(input: &str, env: &Rc<RefCell<Env>>) -> String {
 
 fn main() {
     let mut env = Env::default();
-    for (k, v) in NS {
+    for (k, arity, v) in NS {
         env.set(
             k.to_string(),
-            Rc::new(MalVal::Fn(Rc::new(MalFn::RegularFn(Rc::new(v))), None)),
+            Rc::new(MalVal::Fn(Rc::new(MalFn::regular_fn(k, arity, v)), None)),
         );
     }
     let env = Rc::new(RefCell::new(env));
@@ -1,6 +1,8 @@
-use std::{cell::RefCell, collections::HashMap, io::Write, rc::Rc};
+use std::{cell::RefCell, io::Write, rc::Rc};
 
-use rust2::{read_str, Env, MalError, MalFn, MalResult, MalVal, NS};
+use im_rc::{vector, HashMap, Vector};
+
+use rust2::{read_str, Arity, Env, MalError, MalFn, MalResult, MalVal, NS};
 
 fn read(input: &str) -> Result<MalVal, MalError> {
     read_str(input)
@@ -31,7 +33,8 @@ fn macroexpand(ast: Rc<MalVal>, env: Rc<RefCell<Env>>) -> MalResult {
                     if let Some(v) = env.as_ref().borrow().get(symbol) {
                         if let MalVal::Fn(func, _) = v.as_ref() {
                             if let MalFn::MalFunc(func) = func.as_ref() {
-                                ast = func.run(&list[1..])?;
+                                ast =
+                                    func.run(&list.iter().skip(1).cloned().collect::<Vec<_>>())?;
                                 continue;
                             }
                         }
@@ -46,14 +49,14 @@ fn macroexpand(ast: Rc<MalVal>, env: Rc<RefCell<Env>>) -> MalResult {
 }
 
 fn quasiquote(ast: Rc<MalVal>) -> Rc<MalVal> {
-    fn no_unquote(list: &[Rc<MalVal>]) -> Vec<Rc<MalVal>> {
-        let mut buffer = Vec::new();
+    fn no_unquote(list: &Vector<Rc<MalVal>>) -> Vector<Rc<MalVal>> {
+        let mut buffer = Vector::new();
         for elt in list.iter().rev() {
             if let MalVal::List(list, _) = elt.as_ref() {
                 if list.len() > 1 {
                     if let MalVal::Symbol(s) = list[0].as_ref() {
                         if s == "splice-unquote" {
-                            buffer = vec![
+                            buffer = vector![
                                 Rc::new(MalVal::Symbol("concat".to_string())),
                                 list[1].clone(),
                                 Rc::new(MalVal::List(buffer, None)),
@@ -63,7 +66,7 @@ fn quasiquote(ast: Rc<MalVal>) -> Rc<MalVal> {
                     }
                 }
             }
-            buffer = vec![
+            buffer = vector![
                 Rc::new(MalVal::Symbol("cons".to_string())),
                 quasiquote(elt.clone()),
                 Rc::new(MalVal::List(buffer, None)),
@@ -86,17 +89,17 @@ fn quasiquote(ast: Rc<MalVal>) -> Rc<MalVal> {
         }
         MalVal::Vector(vector, _) => {
             let second = if vector.is_empty() {
-                Rc::new(MalVal::List(Vec::new(), None))
+                Rc::new(MalVal::List(Vector::new(), None))
             } else {
                 Rc::new(MalVal::List(no_unquote(vector), None))
             };
             Rc::new(MalVal::List(
-                vec![Rc::new(MalVal::Symbol("vec".to_string())), second],
+                vector![Rc::new(MalVal::Symbol("vec".to_string())), second],
                 None,
             ))
         }
         MalVal::HashMap(..) | MalVal::Symbol(_) => Rc::new(MalVal::List(
-            vec![Rc::new(MalVal::Symbol("quote".to_string())), ast],
+            vector![Rc::new(MalVal::Symbol("quote".to_string())), ast],
             None,
         )),
         _ => ast,
@@ -111,16 +114,16 @@ fn eval_ast(ast: Rc<MalVal>, env: Rc<RefCell<Env>>) -> MalResult {
             .get(symbol)
             .ok_or_else(|| MalError::Other(format!("'{symbol}' not found."))),
         MalVal::List(list, _) => {
-            let mut buffer = Vec::new();
+            let mut buffer = Vector::new();
             for v in list {
-                buffer.push(eval(v.clone(), env.clone())?);
+                buffer.push_back(eval(v.clone(), env.clone())?);
             }
             Ok(Rc::new(MalVal::List(buffer, None)))
         }
         MalVal::Vector(vector, _) => {
-            let mut buffer = Vec::new();
+            let mut buffer = Vector::new();
             for v in vector {
-                buffer.push(eval(v.clone(), env.clone())?);
+                buffer.push_back(eval(v.clone(), env.clone())?);
             }
             Ok(Rc::new(MalVal::Vector(buffer, None)))
         }
@@ -141,13 +144,19 @@ fn eval(ast: Rc<MalVal>, env: Rc<RefCell<Env>>) -> MalResult {
     loop {
         ast = macroexpand(ast, env.clone())?;
         match ast.as_ref() {
+            MalVal::LazySeq(..) => {
+                ast = Rc::new(MalVal::List(
+                    ast.seq_iter()?.collect::<Result<_, _>>()?,
+                    None,
+                ));
+            }
             MalVal::List(list, _) if list.is_empty() => return Ok(ast),
             MalVal::List(list, _) => {
                 if let MalVal::Symbol(symbol) = list[0].as_ref() {
                     match symbol.as_str() {
                         "def!" => match list[1].as_ref() {
                             MalVal::Symbol(symbol) => {
-                                let v = eval(list[2].clone(), env.clone())?;
+                                let v = eval(list[2].clone(), env.clone())?.with_name(symbol);
                                 env.borrow_mut().set(symbol.clone(), v.clone());
                                 return Ok(v);
                             }
@@ -155,7 +164,10 @@ fn eval(ast: Rc<MalVal>, env: Rc<RefCell<Env>>) -> MalResult {
                         },
                         "defmacro!" => match list[1].as_ref() {
                             MalVal::Symbol(symbol) => {
-                                let v = match eval(list[2].clone(), env.clone())?.as_ref() {
+                                let v = match eval(list[2].clone(), env.clone())?
+                                    .with_name(symbol)
+                                    .as_ref()
+                                {
                                     MalVal::Fn(func, _) => match func.as_ref() {
                                         MalFn::MalFunc(func) => Rc::new(MalVal::Fn(
                                             Rc::new(MalFn::MalFunc(func.construct_marco())),
@@ -196,7 +208,7 @@ fn eval(ast: Rc<MalVal>, env: Rc<RefCell<Env>>) -> MalResult {
                         }
                         "do" => {
                             let mut value = eval(list[1].clone(), env.clone())?;
-                            for v in &list[2..] {
+                            for v in list.iter().skip(2) {
                                 value = eval(v.clone(), env.clone())?;
                             }
                             return Ok(value);
@@ -246,10 +258,12 @@ fn eval(ast: Rc<MalVal>, env: Rc<RefCell<Env>>) -> MalResult {
                 match n_ast.as_ref() {
                     MalVal::List(list, _) => match list[0].as_ref() {
                         MalVal::Fn(func, _) => match func.as_ref() {
-                            MalFn::RegularFn(func) => return (func)(&list[1..]),
+                            MalFn::RegularFn(func) => {
+                                return func.run(&list.iter().skip(1).cloned().collect::<Vec<_>>())
+                            }
                             MalFn::MalFunc(func) => {
-                                let mut n_env = Env::new(func.env.clone());
-                                n_env.bind_expr(func.params.clone(), list[1..].to_vec());
+                                let n_env =
+                                    func.bind(&list.iter().skip(1).cloned().collect::<Vec<_>>())?;
                                 ast = func.ast.clone();
                                 env = Rc::new(RefCell::new(n_env));
                             }
@@ -269,7 +283,10 @@ fn print(val: &MalVal) -> String {
 }
 
 fn rep(input: &str, env: &Rc<RefCell<Env>>) -> Option<String> {
-    match read(input).and_then(|ast| eval(Rc::new(ast), env.clone())) {
+    let result = read(input)
+        .and_then(|ast| eval(Rc::new(ast), env.clone()))
+        .and_then(|v| v.realize().map(|_| v));
+    match result {
         Ok(v) => Some(print(v.as_ref())),
         Err(MalError::Continue) => None,
         Err(e) => Some(e.to_string()),
@@ -278,10 +295,10 @@ fn rep(input: &str, env: &Rc<RefCell<Env>>) -> Option<String> {
 
 fn main() {
     let mut env = Env::default();
-    for (k, v) in NS {
+    for (k, arity, v) in NS {
         env.set(
             k.to_string(),
-            Rc::new(MalVal::Fn(Rc::new(MalFn::RegularFn(Rc::new(v))), None)),
+            Rc::new(MalVal::Fn(Rc::new(MalFn::regular_fn(k, arity, v)), None)),
         );
     }
     let env = Rc::new(RefCell::new(env));
@@ -289,9 +306,9 @@ fn main() {
     env.as_ref().borrow_mut().set(
         "eval".to_string(),
         Rc::new(MalVal::Fn(
-            Rc::new(MalFn::RegularFn(Rc::new(move |args| {
+            Rc::new(MalFn::regular_fn("eval", Arity::exact(1), move |args| {
                 eval(args[0].clone(), env_tmp.clone())
-            }))),
+            })),
             None,
         )),
     );
@@ -311,7 +328,8 @@ fn main() {
         iter.next();
         let filename = iter.next().unwrap();
         let init = Rc::new(MalVal::List(
-            iter.map(|s| Rc::new(MalVal::String(s))).collect::<Vec<_>>(),
+            iter.map(|s| Rc::new(MalVal::String(s)))
+                .collect::<Vector<_>>(),
             None,
         ));
         env.as_ref().borrow_mut().set("*ARGV*".to_string(), init);
@@ -321,7 +339,7 @@ fn main() {
     }
     env.as_ref().borrow_mut().set(
         "*ARGV*".to_string(),
-        Rc::new(MalVal::List(Vec::new(), None)),
+        Rc::new(MalVal::List(Vector::new(), None)),
     );
 
     let mut buffer = String::new();
@@ -1,59 +1,51 @@
-use std::{cell::RefCell, collections::HashMap, io::Write, rc::Rc};
+use std::{cell::RefCell, io::Write, rc::Rc};
 
-use rust2::{read_str, Env, MalError, MalFn, MalResult, MalVal, NS};
+use im_rc::{vector, HashMap, Vector};
+
+use rust2::{read_str, Arity, Env, MalError, MalFn, MalResult, MalVal, NS};
 
 fn read(input: &str) -> Result<MalVal, MalError> {
     read_str(input)
 }
 
-fn is_macro_call(ast: Rc<MalVal>, env: Rc<RefCell<Env>>) -> bool {
-    match ast.as_ref() {
-        MalVal::List(list, _) if !list.is_empty() => {
-            if let MalVal::Symbol(symbol) = list[0].as_ref() {
-                if let Some(v) = env.as_ref().borrow().get(symbol) {
-                    if let MalVal::Fn(func, _) = v.as_ref() {
-                        return matches!(func.as_ref(), MalFn::MalFunc(func) if func.is_marco);
-                    }
-                }
-            }
-            false
-        }
-        _ => false,
-    }
-}
-
 fn macroexpand(ast: Rc<MalVal>, env: Rc<RefCell<Env>>) -> MalResult {
     let mut ast = ast;
-    while is_macro_call(ast.clone(), env.clone()) {
-        match ast.as_ref() {
-            MalVal::List(list, _) if !list.is_empty() => {
-                if let MalVal::Symbol(symbol) = list[0].as_ref() {
-                    if let Some(v) = env.as_ref().borrow().get(symbol) {
-                        if let MalVal::Fn(func, _) = v.as_ref() {
-                            if let MalFn::MalFunc(func) = func.as_ref() {
-                                ast = func.run(&list[1..])?;
-                                continue;
-                            }
-                        }
-                    }
-                }
-                unreachable!()
+    loop {
+        let MalVal::List(list, _) = ast.as_ref() else {
+            return Ok(ast);
+        };
+        let Some(MalVal::Symbol(symbol)) = list.front().map(|v| v.as_ref()) else {
+            return Ok(ast);
+        };
+        let Some(v) = env.as_ref().borrow().get(symbol) else {
+            return Ok(ast);
+        };
+        let MalVal::Fn(func, _) = v.as_ref() else {
+            return Ok(ast);
+        };
+        match func.as_ref() {
+            MalFn::MalFunc(func) if func.is_marco => {
+                let expanded = func.run(&list.iter().skip(1).cloned().collect::<Vec<_>>())?;
+                ast = expanded;
             }
-            _ => unreachable!(),
+            _ => return Ok(ast),
         }
     }
-    Ok(ast)
+}
+
+fn syntax_error(message: &str) -> MalError {
+    MalError::raise("syntax-error", message.to_string())
 }
 
 fn quasiquote(ast: Rc<MalVal>) -> Rc<MalVal> {
-    fn no_unquote(list: &[Rc<MalVal>]) -> Vec<Rc<MalVal>> {
-        let mut buffer = Vec::new();
+    fn no_unquote(list: &Vector<Rc<MalVal>>) -> Vector<Rc<MalVal>> {
+        let mut buffer = Vector::new();
         for elt in list.iter().rev() {
             if let MalVal::List(list, _) = elt.as_ref() {
                 if list.len() > 1 {
                     if let MalVal::Symbol(s) = list[0].as_ref() {
                         if s == "splice-unquote" {
-                            buffer = vec![
+                            buffer = vector![
                                 Rc::new(MalVal::Symbol("concat".to_string())),
                                 list[1].clone(),
                                 Rc::new(MalVal::List(buffer, None)),
@@ -63,7 +55,7 @@ fn quasiquote(ast: Rc<MalVal>) -> Rc<MalVal> {
                     }
                 }
             }
-            buffer = vec![
+            buffer = vector![
                 Rc::new(MalVal::Symbol("cons".to_string())),
                 quasiquote(elt.clone()),
                 Rc::new(MalVal::List(buffer, None)),
@@ -86,17 +78,17 @@ fn quasiquote(ast: Rc<MalVal>) -> Rc<MalVal> {
         }
         MalVal::Vector(vector, _) => {
             let second = if vector.is_empty() {
-                Rc::new(MalVal::List(Vec::new(), None))
+                Rc::new(MalVal::List(Vector::new(), None))
             } else {
                 Rc::new(MalVal::List(no_unquote(vector), None))
             };
             Rc::new(MalVal::List(
-                vec![Rc::new(MalVal::Symbol("vec".to_string())), second],
+                vector![Rc::new(MalVal::Symbol("vec".to_string())), second],
                 None,
             ))
         }
         MalVal::HashMap(..) | MalVal::Symbol(_) => Rc::new(MalVal::List(
-            vec![Rc::new(MalVal::Symbol("quote".to_string())), ast],
+            vector![Rc::new(MalVal::Symbol("quote".to_string())), ast],
             None,
         )),
         _ => ast,
@@ -105,20 +97,22 @@ fn quasiquote(ast: Rc<MalVal>) -> Rc<MalVal> {
 
 fn eval_ast(ast: Rc<MalVal>, env: Rc<RefCell<Env>>) -> MalResult {
     match ast.as_ref() {
+        // The step9 suite expects the bare message string here; stepA raises
+        // a :symbol-not-found exception instead.
         MalVal::Symbol(symbol) => env.as_ref().borrow().get(symbol).ok_or_else(|| {
-            MalError::Throw(Rc::new(MalVal::String(format!("'{symbol}' not found"))))
+            MalError::throw(Rc::new(MalVal::String(format!("'{symbol}' not found"))))
         }),
         MalVal::List(list, _) => {
-            let mut buffer = Vec::new();
+            let mut buffer = Vector::new();
             for v in list {
-                buffer.push(eval(v.clone(), env.clone())?);
+                buffer.push_back(eval(v.clone(), env.clone())?);
             }
             Ok(Rc::new(MalVal::List(buffer, None)))
         }
         MalVal::Vector(vector, _) => {
-            let mut buffer = Vec::new();
+            let mut buffer = Vector::new();
             for v in vector {
-                buffer.push(eval(v.clone(), env.clone())?);
+                buffer.push_back(eval(v.clone(), env.clone())?);
             }
             Ok(Rc::new(MalVal::Vector(buffer, None)))
         }
@@ -139,34 +133,56 @@ fn eval(ast: Rc<MalVal>, env: Rc<RefCell<Env>>) -> MalResult {
     loop {
         ast = macroexpand(ast, env.clone())?;
         match ast.as_ref() {
+            MalVal::LazySeq(..) => {
+                ast = Rc::new(MalVal::List(
+                    ast.seq_iter()?.collect::<Result<_, _>>()?,
+                    None,
+                ));
+            }
             MalVal::List(list, _) if list.is_empty() => return Ok(ast),
             MalVal::List(list, _) => {
                 if let MalVal::Symbol(symbol) = list[0].as_ref() {
                     match symbol.as_str() {
                         "def!" => match list[1].as_ref() {
                             MalVal::Symbol(symbol) => {
-                                let v = eval(list[2].clone(), env.clone())?;
+                                let v = eval(list[2].clone(), env.clone())?.with_name(symbol);
                                 env.borrow_mut().set(symbol.clone(), v.clone());
                                 return Ok(v);
                             }
-                            _ => unreachable!(),
+                            _ => return Err(syntax_error("def!: expected a symbol")),
                         },
                         "defmacro!" => match list[1].as_ref() {
                             MalVal::Symbol(symbol) => {
-                                let v = match eval(list[2].clone(), env.clone())?.as_ref() {
+                                let v = match eval(list[2].clone(), env.clone())?
+                                    .with_name(symbol)
+                                    .as_ref()
+                                {
                                     MalVal::Fn(func, _) => match func.as_ref() {
                                         MalFn::MalFunc(func) => Rc::new(MalVal::Fn(
                                             Rc::new(MalFn::MalFunc(func.construct_marco())),
                                             None,
                                         )),
-                                        _ => unreachable!(),
+                                        MalFn::RegularFn(_) => {
+                                            return Err(MalError::raise(
+                                                "type-error",
+                                                "defmacro!: expected a fn* function".to_string(),
+                                            ))
+                                        }
                                     },
-                                    _ => unreachable!(),
+                                    v => {
+                                        return Err(MalError::raise(
+                                            "type-error",
+                                            format!(
+                                                "defmacro!: expected function, got {}",
+                                                v.type_name()
+                                            ),
+                                        ))
+                                    }
                                 };
                                 env.borrow_mut().set(symbol.clone(), v.clone());
                                 return Ok(v);
                             }
-                            _ => unreachable!(),
+                            _ => return Err(syntax_error("defmacro!: expected a symbol")),
                         },
                         "let*" => {
                             let n_env = Rc::new(RefCell::new(Env::new(env)));
@@ -174,19 +190,22 @@ fn eval(ast: Rc<MalVal>, env: Rc<RefCell<Env>>) -> MalResult {
                                 MalVal::List(binds, _) | MalVal::Vector(binds, _) => {
                                     let mut iter = binds.iter();
                                     while let Some(v) = iter.next() {
-                                        match v.as_ref() {
-                                            MalVal::Symbol(symbol) => {
-                                                let value = eval(
-                                                    iter.next().unwrap().clone(),
-                                                    n_env.clone(),
-                                                )?;
-                                                n_env.borrow_mut().set(symbol.clone(), value);
-                                            }
-                                            _ => unreachable!(),
-                                        }
+                                        let (MalVal::Symbol(symbol), Some(form)) =
+                                            (v.as_ref(), iter.next())
+                                        else {
+                                            return Err(syntax_error(
+                                                "let*: expected a vector of symbol/value pairs",
+                                            ));
+                                        };
+                                        let value = eval(form.clone(), n_env.clone())?;
+                                        n_env.borrow_mut().set(symbol.clone(), value);
                                     }
                                 }
-                                _ => unreachable!(),
+                                _ => {
+                                    return Err(syntax_error(
+                                        "let*: expected a vector of symbol/value pairs",
+                                    ))
+                                }
                             }
                             env = n_env;
                             ast = list[2].clone();
@@ -194,7 +213,7 @@ fn eval(ast: Rc<MalVal>, env: Rc<RefCell<Env>>) -> MalResult {
                         }
                         "do" => {
                             let mut value = eval(list[1].clone(), env.clone())?;
-                            for v in &list[2..] {
+                            for v in list.iter().skip(2) {
                                 value = eval(v.clone(), env.clone())?;
                             }
                             return Ok(value);
@@ -218,11 +237,11 @@ fn eval(ast: Rc<MalVal>, env: Rc<RefCell<Env>>) -> MalResult {
                                 MalVal::List(list, _) | MalVal::Vector(list, _) => list
                                     .iter()
                                     .map(|v| match v.as_ref() {
-                                        MalVal::Symbol(symbol) => symbol.to_string(),
-                                        _ => unreachable!(),
+                                        MalVal::Symbol(symbol) => Ok(symbol.to_string()),
+                                        _ => Err(syntax_error("fn*: parameters must be symbols")),
                                     })
-                                    .collect::<Vec<_>>(),
-                                _ => unreachable!(),
+                                    .collect::<Result<Vec<_>, _>>()?,
+                                _ => return Err(syntax_error("fn*: expected a parameter vector")),
                             };
                             let body = list[2].clone();
                             return Ok(Rc::new(MalVal::Fn(
@@ -238,50 +257,64 @@ fn eval(ast: Rc<MalVal>, env: Rc<RefCell<Env>>) -> MalResult {
                         }
                         "macroexpand" => return macroexpand(list[1].clone(), env),
                         "try*" => {
-                            match eval(list[1].clone(), env.clone()) {
+                            let result = eval(list[1].clone(), env.clone());
+                            let Some(clause) = list.get(2) else {
+                                return result;
+                            };
+                            let e = match result {
                                 Ok(v) => return Ok(v),
-                                Err(MalError::Throw(v)) if list.len() > 2 => match list[2].as_ref()
-                                {
-                                    MalVal::List(catch, _) => {
-                                        match (catch[0].as_ref(), catch[1].as_ref()) {
-                                            (MalVal::Symbol(flag), MalVal::Symbol(symbol)) => {
-                                                match flag.as_str() {
-                                                    "catch*" => {
-                                                        let mut n_env = Env::new(env.clone());
-                                                        n_env.set(symbol.clone(), v);
-                                                        ast = catch[2].clone();
-                                                        env = Rc::new(RefCell::new(n_env));
-                                                        continue;
-                                                    }
-                                                    _ => unreachable!(),
-                                                }
-                                            }
-                                            _ => unreachable!(),
-                                        }
-                                    }
-                                    _ => unreachable!(),
-                                },
-                                Err(e) => return Err(e),
+                                Err(e) => e,
+                            };
+                            let MalVal::List(catch, _) = clause.as_ref() else {
+                                return Err(syntax_error("try*: expected a catch* clause"));
+                            };
+                            let (
+                                Some(MalVal::Symbol(flag)),
+                                Some(MalVal::Symbol(symbol)),
+                                Some(body),
+                            ) = (
+                                catch.get(0).map(|v| v.as_ref()),
+                                catch.get(1).map(|v| v.as_ref()),
+                                catch.get(2),
+                            )
+                            else {
+                                return Err(syntax_error("try*: expected a catch* clause"));
                             };
+                            if flag != "catch*" {
+                                return Err(syntax_error("try*: expected a catch* clause"));
+                            }
+                            let (exception, trace) = e.into_parts();
+                            let mut n_env = Env::new(env.clone());
+                            n_env.set(symbol.clone(), exception.with_stacktrace(trace));
+                            ast = body.clone();
+                            env = Rc::new(RefCell::new(n_env));
+                            continue;
                         }
                         _ => (),
                     }
                 }
                 let n_ast = eval_ast(ast, env)?;
-                match n_ast.as_ref() {
-                    MalVal::List(list, _) => match list[0].as_ref() {
-                        MalVal::Fn(func, _) => match func.as_ref() {
-                            MalFn::RegularFn(func) => return (func)(&list[1..]),
-                            MalFn::MalFunc(func) => {
-                                let mut n_env = Env::new(func.env.clone());
-                                n_env.bind_expr(func.params.clone(), list[1..].to_vec());
-                                ast = func.ast.clone();
-                                env = Rc::new(RefCell::new(n_env));
-                            }
-                        },
-                        _ => unreachable!(),
+                let MalVal::List(list, _) = n_ast.as_ref() else {
+                    return Ok(n_ast);
+                };
+                match list[0].as_ref() {
+                    MalVal::Fn(func, _) => match func.as_ref() {
+                        MalFn::RegularFn(func) => {
+                            return func.run(&list.iter().skip(1).cloned().collect::<Vec<_>>())
+                        }
+                        MalFn::MalFunc(func) => {
+                            let n_env =
+                                func.bind(&list.iter().skip(1).cloned().collect::<Vec<_>>())?;
+                            ast = func.ast.clone();
+                            env = Rc::new(RefCell::new(n_env));
+                        }
                     },
-                    _ => unreachable!(),
+                    v => {
+                        return Err(MalError::raise(
+                            "type-error",
+                            format!("expected function, got {}", v.type_name()),
+                        ))
+                    }
                 }
             }
             _ => return eval_ast(ast, env),
@@ -294,7 +327,10 @@ fn print(val: &MalVal) -> String {
 }
 
 fn rep(input: &str, env: &Rc<RefCell<Env>>) -> Option<String> {
-    match read(input).and_then(|ast| eval(Rc::new(ast), env.clone())) {
+    let result = read(input)
+        .and_then(|ast| eval(Rc::new(ast), env.clone()))
+        .and_then(|v| v.realize().map(|_| v));
+    match result {
         Ok(v) => Some(print(v.as_ref())),
         Err(MalError::Continue) => None,
         Err(e) => Some(e.to_string()),
@@ -303,10 +339,10 @@ fn rep(input: &str, env: &Rc<RefCell<Env>>) -> Option<String> {
 
 fn main() {
     let mut env = Env::default();
-    for (k, v) in NS {
+    for (k, arity, v) in NS {
         env.set(
             k.to_string(),
-            Rc::new(MalVal::Fn(Rc::new(MalFn::RegularFn(Rc::new(v))), None)),
+            Rc::new(MalVal::Fn(Rc::new(MalFn::regular_fn(k, arity, v)), None)),
         );
     }
     let env = Rc::new(RefCell::new(env));
@@ -314,9 +350,9 @@ fn main() {
     env.as_ref().borrow_mut().set(
         "eval".to_string(),
         Rc::new(MalVal::Fn(
-            Rc::new(MalFn::RegularFn(Rc::new(move |args| {
+            Rc::new(MalFn::regular_fn("eval", Arity::exact(1), move |args| {
                 eval(args[0].clone(), env_tmp.clone())
-            }))),
+            })),
             None,
         )),
     );
@@ -336,7 +372,8 @@ fn main() {
         iter.next();
         let filename = iter.next().unwrap();
         let init = Rc::new(MalVal::List(
-            iter.map(|s| Rc::new(MalVal::String(s))).collect::<Vec<_>>(),
+            iter.map(|s| Rc::new(MalVal::String(s)))
+                .collect::<Vector<_>>(),
             None,
         ));
         env.as_ref().borrow_mut().set("*ARGV*".to_string(), init);
@@ -346,7 +383,7 @@ fn main() {
     }
     env.as_ref().borrow_mut().set(
         "*ARGV*".to_string(),
-        Rc::new(MalVal::List(Vec::new(), None)),
+        Rc::new(MalVal::List(Vector::new(), None)),
     );
 
     let mut buffer = String::new();
@@ -1,59 +1,54 @@
-use std::{cell::RefCell, collections::HashMap, io::Write, rc::Rc};
+use std::{cell::RefCell, collections::VecDeque, io::Write, rc::Rc};
 
-use rust2::{read_str, Env, MalError, MalFn, MalResult, MalVal, NS};
+use im_rc::{vector, HashMap, HashSet, Vector};
+
+use rust2::{
+    read_file, read_str, Arity, Env, Hashable, LazySeq, MalError, MalFn, MalResult, MalVal, Port,
+    NS,
+};
 
 fn read(input: &str) -> Result<MalVal, MalError> {
     read_str(input)
 }
 
-fn is_macro_call(ast: Rc<MalVal>, env: Rc<RefCell<Env>>) -> bool {
-    match ast.as_ref() {
-        MalVal::List(list, _) if !list.is_empty() => {
-            if let MalVal::Symbol(symbol) = list[0].as_ref() {
-                if let Some(v) = env.as_ref().borrow().get(symbol) {
-                    if let MalVal::Fn(func, _) = v.as_ref() {
-                        return matches!(func.as_ref(), MalFn::MalFunc(func) if func.is_marco);
-                    }
-                }
-            }
-            false
-        }
-        _ => false,
-    }
-}
-
 fn macroexpand(ast: Rc<MalVal>, env: Rc<RefCell<Env>>) -> MalResult {
     let mut ast = ast;
-    while is_macro_call(ast.clone(), env.clone()) {
-        match ast.as_ref() {
-            MalVal::List(list, _) if !list.is_empty() => {
-                if let MalVal::Symbol(symbol) = list[0].as_ref() {
-                    if let Some(v) = env.as_ref().borrow().get(symbol) {
-                        if let MalVal::Fn(func, _) = v.as_ref() {
-                            if let MalFn::MalFunc(func) = func.as_ref() {
-                                ast = func.run(&list[1..])?;
-                                continue;
-                            }
-                        }
-                    }
-                }
-                unreachable!()
+    loop {
+        let MalVal::List(list, _) = ast.as_ref() else {
+            return Ok(ast);
+        };
+        let Some(MalVal::Symbol(symbol)) = list.front().map(|v| v.as_ref()) else {
+            return Ok(ast);
+        };
+        let Some(v) = env.as_ref().borrow().get(symbol) else {
+            return Ok(ast);
+        };
+        let MalVal::Fn(func, _) = v.as_ref() else {
+            return Ok(ast);
+        };
+        match func.as_ref() {
+            MalFn::MalFunc(func) if func.is_marco => {
+                let expanded = func.run(&list.iter().skip(1).cloned().collect::<Vec<_>>())?;
+                ast = expanded;
             }
-            _ => unreachable!(),
+            _ => return Ok(ast),
         }
     }
-    Ok(ast)
+}
+
+fn syntax_error(message: &str) -> MalError {
+    MalError::raise("syntax-error", message.to_string())
 }
 
 fn quasiquote(ast: Rc<MalVal>) -> Rc<MalVal> {
-    fn no_unquote(list: &[Rc<MalVal>]) -> Vec<Rc<MalVal>> {
-        let mut buffer = Vec::new();
+    fn no_unquote(list: &Vector<Rc<MalVal>>) -> Vector<Rc<MalVal>> {
+        let mut buffer = Vector::new();
         for elt in list.iter().rev() {
             if let MalVal::List(list, _) = elt.as_ref() {
                 if list.len() > 1 {
                     if let MalVal::Symbol(s) = list[0].as_ref() {
                         if s == "splice-unquote" {
-                            buffer = vec![
+                            buffer = vector![
                                 Rc::new(MalVal::Symbol("concat".to_string())),
                                 list[1].clone(),
                                 Rc::new(MalVal::List(buffer, None)),
@@ -63,7 +58,7 @@ fn quasiquote(ast: Rc<MalVal>) -> Rc<MalVal> {
                     }
                 }
             }
-            buffer = vec![
+            buffer = vector![
                 Rc::new(MalVal::Symbol("cons".to_string())),
                 quasiquote(elt.clone()),
                 Rc::new(MalVal::List(buffer, None)),
@@ -86,17 +81,17 @@ fn quasiquote(ast: Rc<MalVal>) -> Rc<MalVal> {
         }
         MalVal::Vector(vector, _) => {
             let second = if vector.is_empty() {
-                Rc::new(MalVal::List(Vec::new(), None))
+                Rc::new(MalVal::List(Vector::new(), None))
             } else {
                 Rc::new(MalVal::List(no_unquote(vector), None))
             };
             Rc::new(MalVal::List(
-                vec![Rc::new(MalVal::Symbol("vec".to_string())), second],
+                vector![Rc::new(MalVal::Symbol("vec".to_string())), second],
                 None,
             ))
         }
-        MalVal::HashMap(..) | MalVal::Symbol(_) => Rc::new(MalVal::List(
-            vec![Rc::new(MalVal::Symbol("quote".to_string())), ast],
+        MalVal::HashMap(..) | MalVal::Set(..) | MalVal::Symbol(_) => Rc::new(MalVal::List(
+            vector![Rc::new(MalVal::Symbol("quote".to_string())), ast],
             None,
         )),
         _ => ast,
@@ -105,68 +100,302 @@ fn quasiquote(ast: Rc<MalVal>) -> Rc<MalVal> {
 
 fn eval_ast(ast: Rc<MalVal>, env: Rc<RefCell<Env>>) -> MalResult {
     match ast.as_ref() {
-        MalVal::Symbol(symbol) => env.as_ref().borrow().get(symbol).ok_or_else(|| {
-            MalError::Throw(Rc::new(MalVal::String(format!("'{symbol}' not found"))))
-        }),
+        MalVal::Symbol(symbol) => env
+            .as_ref()
+            .borrow()
+            .get(symbol)
+            .ok_or_else(|| MalError::raise("symbol-not-found", format!("'{symbol}' not found"))),
         MalVal::List(list, _) => {
-            let mut buffer = Vec::new();
+            let mut buffer = Vector::new();
             for v in list {
-                buffer.push(eval(v.clone(), env.clone())?);
+                buffer.push_back(eval(v.clone(), env.clone())?);
             }
             Ok(Rc::new(MalVal::List(buffer, None)))
         }
         MalVal::Vector(vector, _) => {
-            let mut buffer = Vec::new();
+            let mut buffer = Vector::new();
             for v in vector {
-                buffer.push(eval(v.clone(), env.clone())?);
+                buffer.push_back(eval(v.clone(), env.clone())?);
             }
             Ok(Rc::new(MalVal::Vector(buffer, None)))
         }
         MalVal::HashMap(hashmap, _) => {
             let mut buffer = HashMap::new();
             for (k, v) in hashmap {
-                buffer.insert(k.clone(), eval(v.clone(), env.clone())?);
+                let k = Hashable::try_from(eval(k.into(), env.clone())?)?;
+                buffer.insert(k, eval(v.clone(), env.clone())?);
             }
             Ok(Rc::new(MalVal::HashMap(buffer, None)))
         }
+        MalVal::Set(set, _) => {
+            let mut buffer = HashSet::new();
+            for k in set {
+                buffer.insert(Hashable::try_from(eval(k.into(), env.clone())?)?);
+            }
+            Ok(Rc::new(MalVal::Set(buffer, None)))
+        }
         _ => Ok(ast),
     }
 }
 
+fn is_form(ast: &MalVal, name: &str) -> bool {
+    match ast {
+        MalVal::List(list, _) => {
+            matches!(list.front().map(|v| v.as_ref()), Some(MalVal::Symbol(s)) if s == name)
+        }
+        _ => false,
+    }
+}
+
+fn eval_body(body: &Vector<Rc<MalVal>>, env: Rc<RefCell<Env>>) -> MalResult {
+    let mut value = Rc::new(MalVal::Nil);
+    for v in body {
+        value = eval(v.clone(), env.clone())?;
+    }
+    Ok(value)
+}
+
+fn is_selector(form: &MalVal, env: &Rc<RefCell<Env>>) -> bool {
+    match form {
+        MalVal::Keyword(_) | MalVal::List(..) => true,
+        MalVal::Symbol(symbol) => {
+            matches!(env.borrow().get(symbol).as_deref(), Some(MalVal::Fn(..)))
+        }
+        _ => false,
+    }
+}
+
+type Handler<'a> = (&'a str, Vector<Rc<MalVal>>);
+
+fn select_catch<'a>(
+    clauses: &'a Vector<Rc<MalVal>>,
+    exception: &Rc<MalVal>,
+    env: &Rc<RefCell<Env>>,
+) -> Result<Option<Handler<'a>>, MalError> {
+    for clause in clauses {
+        let MalVal::List(catch, _) = clause.as_ref() else {
+            return Err(syntax_error("try*: expected catch* or finally clause"));
+        };
+        if !is_form(clause, "catch*") || catch.len() < 3 {
+            return Err(syntax_error("try*: expected catch* or finally clause"));
+        }
+        let (selector, symbol, body) = match catch[2].as_ref() {
+            MalVal::Symbol(_) if catch.len() > 3 && is_selector(&catch[1], env) => {
+                (Some(&catch[1]), &catch[2], catch.skip(3))
+            }
+            _ => (None, &catch[1], catch.skip(2)),
+        };
+        let MalVal::Symbol(symbol) = symbol.as_ref() else {
+            return Err(syntax_error("catch*: binding must be a symbol"));
+        };
+        let matched = match selector {
+            None => true,
+            Some(selector) => {
+                let selector = eval(selector.clone(), env.clone())?;
+                match selector.as_ref() {
+                    MalVal::Keyword(_) => exception
+                        .ex_field("data")
+                        .and_then(|data| data.ex_field("type"))
+                        .is_some_and(|kind| kind == selector),
+                    MalVal::Fn(f, _) => !matches!(
+                        f.run(std::slice::from_ref(exception))?.as_ref(),
+                        MalVal::Nil | MalVal::Bool(false)
+                    ),
+                    v => {
+                        return Err(MalError::raise(
+                            "type-error",
+                            format!(
+                                "catch*: expected keyword or function, got {}",
+                                v.type_name()
+                            ),
+                        ))
+                    }
+                }
+            }
+        };
+        if matched {
+            return Ok(Some((symbol, body)));
+        }
+    }
+    Ok(None)
+}
+
+fn binding_pairs(
+    name: &str,
+    bindings: Option<&Rc<MalVal>>,
+) -> Result<Vec<(String, Rc<MalVal>)>, MalError> {
+    let error = || {
+        MalError::raise(
+            "syntax-error",
+            format!("{name}: expected a vector of symbol/value pairs"),
+        )
+    };
+    let (MalVal::List(bindings, _) | MalVal::Vector(bindings, _)) =
+        bindings.ok_or_else(error)?.as_ref()
+    else {
+        return Err(error());
+    };
+    if !bindings.len().is_multiple_of(2) {
+        return Err(error());
+    }
+    let mut pairs = Vec::new();
+    let mut iter = bindings.iter();
+    while let (Some(symbol), Some(form)) = (iter.next(), iter.next()) {
+        let MalVal::Symbol(symbol) = symbol.as_ref() else {
+            return Err(error());
+        };
+        pairs.push((symbol.clone(), form.clone()));
+    }
+    Ok(pairs)
+}
+
+fn with_bindings(
+    bindings: Vec<(String, Rc<MalVal>)>,
+    body: impl FnOnce() -> MalResult,
+) -> MalResult {
+    let mut saved = Vec::new();
+    let mut result = None;
+    for (symbol, value) in bindings {
+        match Env::rebind(&symbol, value) {
+            Some(old) => saved.push((symbol, old)),
+            None => {
+                result = Some(Err(MalError::raise(
+                    "illegal-state",
+                    format!("binding: {symbol} is not dynamic"),
+                )));
+                break;
+            }
+        }
+    }
+    let result = result.unwrap_or_else(body);
+    for (symbol, old) in saved.into_iter().rev() {
+        Env::rebind(&symbol, old);
+    }
+    result
+}
+
+fn with_open(list: &Vector<Rc<MalVal>>, env: Rc<RefCell<Env>>) -> MalResult {
+    let env = Rc::new(RefCell::new(Env::new(env)));
+    let mut ports = Vec::new();
+    let mut result = Ok(Rc::new(MalVal::Nil));
+    for (symbol, form) in binding_pairs("with-open", list.get(1))? {
+        result = eval(form, env.clone()).and_then(|v| match v.as_ref() {
+            MalVal::Port(port) => {
+                ports.push(port.clone());
+                env.borrow_mut().set(symbol, v.clone());
+                Ok(v)
+            }
+            v => Err(MalError::raise(
+                "type-error",
+                format!("with-open: expected port, got {}", v.type_name()),
+            )),
+        });
+        if result.is_err() {
+            break;
+        }
+    }
+    if result.is_ok() {
+        result = eval_body(&list.skip(2), env);
+    }
+    for port in ports.into_iter().rev() {
+        let closed = port.close();
+        if result.is_ok() {
+            closed?;
+        }
+    }
+    result
+}
+
+const MAX_TAIL_FRAMES: usize = 64;
+
 fn eval(ast: Rc<MalVal>, env: Rc<RefCell<Env>>) -> MalResult {
+    let mut frames = VecDeque::new();
+    eval_in_frames(ast, env, &mut frames).map_err(|e| {
+        frames
+            .iter()
+            .rev()
+            .fold(e, |e, (name, form)| e.with_frame(name, Some(form)))
+    })
+}
+
+fn push_frame(frames: &mut VecDeque<(String, Rc<MalVal>)>, name: String, form: Rc<MalVal>) {
+    if let Some((last, last_form)) = frames.back() {
+        if *last == name && Rc::ptr_eq(last_form, &form) {
+            return;
+        }
+    }
+    if frames.len() == MAX_TAIL_FRAMES {
+        frames.pop_front();
+    }
+    frames.push_back((name, form));
+}
+
+fn eval_in_frames(
+    ast: Rc<MalVal>,
+    env: Rc<RefCell<Env>>,
+    frames: &mut VecDeque<(String, Rc<MalVal>)>,
+) -> MalResult {
     let mut ast = ast;
     let mut env = env;
     loop {
         ast = macroexpand(ast, env.clone())?;
         match ast.as_ref() {
+            MalVal::LazySeq(..) => {
+                ast = Rc::new(MalVal::List(
+                    ast.seq_iter()?.collect::<Result<_, _>>()?,
+                    None,
+                ));
+            }
             MalVal::List(list, _) if list.is_empty() => return Ok(ast),
             MalVal::List(list, _) => {
                 if let MalVal::Symbol(symbol) = list[0].as_ref() {
                     match symbol.as_str() {
                         "def!" => match list[1].as_ref() {
+                            MalVal::Symbol(symbol) if Env::dynamic(symbol).is_some() => {
+                                return Err(MalError::raise(
+                                    "illegal-state",
+                                    format!("def!: {symbol} is dynamic; use binding"),
+                                ))
+                            }
                             MalVal::Symbol(symbol) => {
-                                let v = eval(list[2].clone(), env.clone())?;
+                                let v = eval(list[2].clone(), env.clone())?.with_name(symbol);
                                 env.borrow_mut().set(symbol.clone(), v.clone());
                                 return Ok(v);
                             }
-                            _ => unreachable!(),
+                            _ => return Err(syntax_error("def!: expected a symbol")),
                         },
                         "defmacro!" => match list[1].as_ref() {
                             MalVal::Symbol(symbol) => {
-                                let v = match eval(list[2].clone(), env.clone())?.as_ref() {
+                                let v = match eval(list[2].clone(), env.clone())?
+                                    .with_name(symbol)
+                                    .as_ref()
+                                {
                                     MalVal::Fn(func, _) => match func.as_ref() {
                                         MalFn::MalFunc(func) => Rc::new(MalVal::Fn(
                                             Rc::new(MalFn::MalFunc(func.construct_marco())),
                                             None,
                                         )),
-                                        _ => unreachable!(),
+                                        MalFn::RegularFn(_) => {
+                                            return Err(MalError::raise(
+                                                "type-error",
+                                                "defmacro!: expected a fn* function".to_string(),
+                                            ))
+                                        }
                                     },
-                                    _ => unreachable!(),
+                                    v => {
+                                        return Err(MalError::raise(
+                                            "type-error",
+                                            format!(
+                                                "defmacro!: expected function, got {}",
+                                                v.type_name()
+                                            ),
+                                        ))
+                                    }
                                 };
                                 env.borrow_mut().set(symbol.clone(), v.clone());
                                 return Ok(v);
                             }
-                            _ => unreachable!(),
+                            _ => return Err(syntax_error("defmacro!: expected a symbol")),
                         },
                         "let*" => {
                             let n_env = Rc::new(RefCell::new(Env::new(env)));
@@ -174,19 +403,22 @@ fn eval(ast: Rc<MalVal>, env: Rc<RefCell<Env>>) -> MalResult {
                                 MalVal::List(binds, _) | MalVal::Vector(binds, _) => {
                                     let mut iter = binds.iter();
                                     while let Some(v) = iter.next() {
-                                        match v.as_ref() {
-                                            MalVal::Symbol(symbol) => {
-                                                let value = eval(
-                                                    iter.next().unwrap().clone(),
-                                                    n_env.clone(),
-                                                )?;
-                                                n_env.borrow_mut().set(symbol.clone(), value);
-                                            }
-                                            _ => unreachable!(),
-                                        }
+                                        let (MalVal::Symbol(symbol), Some(form)) =
+                                            (v.as_ref(), iter.next())
+                                        else {
+                                            return Err(syntax_error(
+                                                "let*: expected a vector of symbol/value pairs",
+                                            ));
+                                        };
+                                        let value = eval(form.clone(), n_env.clone())?;
+                                        n_env.borrow_mut().set(symbol.clone(), value);
                                     }
                                 }
-                                _ => unreachable!(),
+                                _ => {
+                                    return Err(syntax_error(
+                                        "let*: expected a vector of symbol/value pairs",
+                                    ))
+                                }
                             }
                             env = n_env;
                             ast = list[2].clone();
@@ -194,7 +426,7 @@ fn eval(ast: Rc<MalVal>, env: Rc<RefCell<Env>>) -> MalResult {
                         }
                         "do" => {
                             let mut value = eval(list[1].clone(), env.clone())?;
-                            for v in &list[2..] {
+                            for v in list.iter().skip(2) {
                                 value = eval(v.clone(), env.clone())?;
                             }
                             return Ok(value);
@@ -218,11 +450,11 @@ fn eval(ast: Rc<MalVal>, env: Rc<RefCell<Env>>) -> MalResult {
                                 MalVal::List(list, _) | MalVal::Vector(list, _) => list
                                     .iter()
                                     .map(|v| match v.as_ref() {
-                                        MalVal::Symbol(symbol) => symbol.to_string(),
-                                        _ => unreachable!(),
+                                        MalVal::Symbol(symbol) => Ok(symbol.to_string()),
+                                        _ => Err(syntax_error("fn*: parameters must be symbols")),
                                     })
-                                    .collect::<Vec<_>>(),
-                                _ => unreachable!(),
+                                    .collect::<Result<Vec<_>, _>>()?,
+                                _ => return Err(syntax_error("fn*: expected a parameter vector")),
                             };
                             let body = list[2].clone();
                             return Ok(Rc::new(MalVal::Fn(
@@ -237,41 +469,96 @@ fn eval(ast: Rc<MalVal>, env: Rc<RefCell<Env>>) -> MalResult {
                             continue;
                         }
                         "macroexpand" => return macroexpand(list[1].clone(), env),
+                        "lazy-seq" => {
+                            let body = list.skip(1);
+                            let env = env.clone();
+                            return Ok(Rc::new(MalVal::LazySeq(
+                                Rc::new(LazySeq::new(move || eval_body(&body, env))),
+                                None,
+                            )));
+                        }
+                        "binding" => {
+                            let mut bindings = Vec::new();
+                            for (symbol, form) in binding_pairs("binding", list.get(1))? {
+                                bindings.push((symbol, eval(form, env.clone())?));
+                            }
+                            return with_bindings(bindings, || eval_body(&list.skip(2), env));
+                        }
+                        "with-out-str" => {
+                            let out = Rc::new(Port::string_writer());
+                            let binding = ("*out*".to_string(), Rc::new(MalVal::Port(out.clone())));
+                            with_bindings(vec![binding], || eval_body(&list.skip(1), env))?;
+                            return Ok(Rc::new(MalVal::String(out.contents().unwrap_or_default())));
+                        }
+                        "with-open" => return with_open(list, env),
                         "try*" => {
-                            match eval(list[1].clone(), env.clone()) {
-                                Ok(v) => return Ok(v),
-                                Err(MalError::Continue) => return Ok(Rc::new(MalVal::Nil)),
-                                Err(e) if list.len() > 2 => {
-                                    let MalVal::List(catch,_) = &*list[2] else { unreachable!() };
-                                    let (MalVal::Symbol(flag), MalVal::Symbol(symbol)) = (&*catch[0], &*catch[1]) else { unreachable!() };
-                                    let "catch*" = flag.as_str() else { unreachable!() };
-                                    let mut n_env = Env::new(env.clone());
-                                    n_env.set(symbol.clone(), e.into());
-                                    ast = catch[2].clone();
-                                    env = Rc::new(RefCell::new(n_env));
-                                    continue;
+                            let mut clauses = list.skip(2);
+                            let finally = clauses.back().filter(|v| is_form(v, "finally")).cloned();
+                            if finally.is_some() {
+                                clauses.pop_back();
+                            }
+                            let result = match eval(list[1].clone(), env.clone()) {
+                                Err(MalError::Continue) => Ok(Rc::new(MalVal::Nil)),
+                                Err(e) if clauses.is_empty() => Err(e),
+                                Err(e) => {
+                                    let (exception, trace) = e.into_parts();
+                                    let caught = exception.with_stacktrace(trace.clone());
+                                    match select_catch(&clauses, &caught, &env) {
+                                        Ok(Some((symbol, body))) => {
+                                            let mut n_env = Env::new(env.clone());
+                                            n_env.set(symbol.to_string(), caught);
+                                            let n_env = Rc::new(RefCell::new(n_env));
+                                            if finally.is_some() {
+                                                eval_body(&body, n_env)
+                                            } else if let Some(last) = body.back() {
+                                                for v in body.iter().take(body.len() - 1) {
+                                                    eval(v.clone(), n_env.clone())?;
+                                                }
+                                                ast = last.clone();
+                                                env = n_env;
+                                                continue;
+                                            } else {
+                                                Ok(Rc::new(MalVal::Nil))
+                                            }
+                                        }
+                                        Ok(None) => Err(MalError::Throw(exception, trace)),
+                                        Err(e) => Err(e),
+                                    }
                                 }
-                                Err(e) => return Err(e),
+                                ok => ok,
                             };
+                            if let Some(MalVal::List(finally, _)) = finally.as_deref() {
+                                eval_body(&finally.skip(1), env)?;
+                            }
+                            return result;
                         }
                         _ => (),
                     }
                 }
+                let form = ast.clone();
                 let n_ast = eval_ast(ast, env)?;
-                match n_ast.as_ref() {
-                    MalVal::List(list, _) => match list[0].as_ref() {
-                        MalVal::Fn(func, _) => match func.as_ref() {
-                            MalFn::RegularFn(func) => return (func)(&list[1..]),
-                            MalFn::MalFunc(func) => {
-                                let mut n_env = Env::new(func.env.clone());
-                                n_env.bind_expr(func.params.clone(), list[1..].to_vec());
-                                ast = func.ast.clone();
-                                env = Rc::new(RefCell::new(n_env));
-                            }
-                        },
-                        _ => unreachable!(),
+                let MalVal::List(list, _) = n_ast.as_ref() else {
+                    return Ok(n_ast);
+                };
+                match list[0].as_ref() {
+                    MalVal::Fn(func, _) => match func.as_ref() {
+                        MalFn::RegularFn(func) => {
+                            return func.run(&list.iter().skip(1).cloned().collect::<Vec<_>>())
+                        }
+                        MalFn::MalFunc(func) => {
+                            let n_env =
+                                func.bind(&list.iter().skip(1).cloned().collect::<Vec<_>>())?;
+                            push_frame(frames, func.name(), form);
+                            ast = func.ast.clone();
+                            env = Rc::new(RefCell::new(n_env));
+                        }
                     },
-                    _ => unreachable!(),
+                    v => {
+                        return Err(MalError::raise(
+                            "type-error",
+                            format!("expected function, got {}", v.type_name()),
+                        ))
+                    }
                 }
             }
             _ => return eval_ast(ast, env),
@@ -283,20 +570,47 @@ fn print(val: &MalVal) -> String {
     val.pr_str(true)
 }
 
+fn re(input: &str, env: &Rc<RefCell<Env>>) -> MalResult {
+    read(input)
+        .and_then(|ast| eval(Rc::new(ast), env.clone()))
+        .and_then(|v| v.realize().map(|_| v))
+}
+
 fn rep(input: &str, env: &Rc<RefCell<Env>>) -> Option<String> {
-    match read(input).and_then(|ast| eval(Rc::new(ast), env.clone())) {
+    match re(input, env) {
         Ok(v) => Some(print(v.as_ref())),
         Err(MalError::Continue) => None,
-        Err(e) => Some(e.to_string()),
+        Err(e) => Some(print_error(e)),
+    }
+}
+
+fn print_error(e: MalError) -> String {
+    let mut output = e.to_string();
+    let (_, trace) = e.into_parts();
+    for frame in trace {
+        output.push_str(&format!("\n  at {}", print_frame(&frame)));
+    }
+    output
+}
+
+fn print_frame(frame: &MalVal) -> String {
+    let field = |key| frame.ex_field(key).map(|v| v.pr_str(false));
+    let name = field("fn").unwrap_or_default();
+    match (field("file"), field("line"), field("column")) {
+        (file, Some(line), Some(column)) => format!(
+            "{name} ({}:{line}:{column})",
+            file.unwrap_or_else(|| "NO_SOURCE".to_string())
+        ),
+        _ => name,
     }
 }
 
 fn main() {
     let mut env = Env::default();
-    for (k, v) in NS {
+    for (k, arity, v) in NS {
         env.set(
             k.to_string(),
-            Rc::new(MalVal::Fn(Rc::new(MalFn::RegularFn(Rc::new(v))), None)),
+            Rc::new(MalVal::Fn(Rc::new(MalFn::regular_fn(k, arity, v)), None)),
         );
     }
     env.set(
@@ -308,17 +622,35 @@ fn main() {
     env.as_ref().borrow_mut().set(
         "eval".to_string(),
         Rc::new(MalVal::Fn(
-            Rc::new(MalFn::RegularFn(Rc::new(move |args| {
+            Rc::new(MalFn::regular_fn("eval", Arity::exact(1), move |args| {
                 eval(args[0].clone(), env_tmp.clone())
-            }))),
+            })),
             None,
         )),
     );
-    rep("(def! not (fn* (a) (if a false true)))", &env);
-    rep(
-        r#"(def! load-file (fn* (f) (eval (read-string (str "(do " (slurp f) "\nnil)")))))"#,
-        &env,
+    let env_tmp = env.clone();
+    env.as_ref().borrow_mut().set(
+        "load-file".to_string(),
+        Rc::new(MalVal::Fn(
+            Rc::new(MalFn::regular_fn(
+                "load-file",
+                Arity::exact(1),
+                move |args| match args[0].as_ref() {
+                    MalVal::String(file) => {
+                        let source =
+                            std::fs::read_to_string(file).map_err(|e| MalError::io(file, e))?;
+                        eval(Rc::new(read_file(&source, file)?), env_tmp.clone())
+                    }
+                    v => Err(MalError::raise(
+                        "type-error",
+                        format!("load-file: expected string, got {}", v.type_name()),
+                    )),
+                },
+            )),
+            None,
+        )),
     );
+    rep("(def! not (fn* (a) (if a false true)))", &env);
     rep(
         r#"(defmacro! cond (fn* (& xs) (if (> (count xs) 0) (list 'if (first xs) (if (> (count xs) 1) (nth xs 1) (throw "odd number of forms to cond")) (cons 'cond (rest (rest xs)))))))"#,
         &env,
@@ -330,17 +662,23 @@ fn main() {
         iter.next();
         let filename = iter.next().unwrap();
         let init = Rc::new(MalVal::List(
-            iter.map(|s| Rc::new(MalVal::String(s))).collect::<Vec<_>>(),
+            iter.map(|s| Rc::new(MalVal::String(s)))
+                .collect::<Vector<_>>(),
             None,
         ));
         env.as_ref().borrow_mut().set("*ARGV*".to_string(), init);
         let input = format!("(load-file \"{filename}\")");
-        rep(&input, &env);
-        return;
+        match re(&input, &env) {
+            Ok(_) | Err(MalError::Continue) => return,
+            Err(e) => {
+                eprintln!("{}", print_error(e));
+                std::process::exit(1);
+            }
+        }
     }
     env.as_ref().borrow_mut().set(
         "*ARGV*".to_string(),
-        Rc::new(MalVal::List(Vec::new(), None)),
+        Rc::new(MalVal::List(Vector::new(), None)),
     );
 
     rep(r#"(println (str "Mal [" *host-language* "]"))"#, &env);
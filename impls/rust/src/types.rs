@@ -1,23 +1,34 @@
 use std::{
-    cell::{Cell, RefCell},
-    collections::HashMap,
+    cell::{Cell, OnceCell, RefCell},
+    cmp::Ordering,
+    collections::hash_map::DefaultHasher,
     fmt::{Debug, Display},
+    hash::{Hash, Hasher},
+    io::{BufRead, Write},
+    ops::Deref,
     rc::Rc,
 };
 
+use im_rc::{HashMap, HashSet, Vector};
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::ToPrimitive;
+use regex::Regex;
 use thiserror::Error;
 
 use crate::Env;
 
 pub type MalResult = Result<Rc<MalVal>, MalError>;
 
+pub type Trace = Vector<Rc<MalVal>>;
+
 #[derive(Error, Debug)]
 pub enum MalError {
     #[error("Eexception {0}")]
-    Throw(Rc<MalVal>),
+    Throw(Rc<MalVal>, Trace),
 
-    #[error("EOF")]
-    Unbalance(&'static str),
+    #[error(transparent)]
+    Read(#[from] ReadError),
 
     #[error("no more token, need continue")]
     Continue,
@@ -26,54 +37,271 @@ pub enum MalError {
     Other(String),
 }
 
+#[derive(Error, Debug)]
+#[error("{message}")]
+pub struct ReadError {
+    pub message: String,
+    pub file: Option<String>,
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Clone, Copy)]
+pub struct Arity {
+    pub min: usize,
+    pub max: Option<usize>,
+}
+
+#[derive(Clone)]
 pub struct MalFunc {
+    pub name: Option<String>,
     pub ast: Rc<MalVal>,
     pub params: Vec<String>,
+    pub arity: Arity,
     pub env: Rc<RefCell<Env>>,
     pub func: fn(Rc<MalVal>, Rc<RefCell<Env>>) -> MalResult,
     pub is_marco: bool,
 }
 
+pub type Builtin = dyn Fn(&[Rc<MalVal>]) -> MalResult;
+
+pub struct RegularFunc {
+    pub name: String,
+    pub arity: Arity,
+    pub func: Rc<Builtin>,
+}
+
+#[derive(Clone)]
+pub struct SortedMap {
+    entries: Vector<(Rc<MalVal>, Rc<MalVal>)>,
+    comparator: Option<Rc<MalVal>>,
+}
+
+#[derive(Clone)]
+pub struct MalRegex {
+    regex: Regex,
+    anchored: OnceCell<Regex>,
+}
+
+pub type SeqStep = Option<(Rc<MalVal>, Rc<MalVal>)>;
+
+pub struct LazySeq(RefCell<LazyState>);
+
+enum LazyState {
+    Pending(Box<dyn FnOnce() -> MalResult>),
+    Realizing,
+    Realized(SeqStep),
+    Failed(Rc<MalVal>, Trace),
+}
+
+pub struct Port {
+    name: String,
+    state: RefCell<PortState>,
+}
+
+enum PortState {
+    Stdin,
+    Stdout,
+    Stderr,
+    Reader(Box<dyn BufRead>),
+    Writer(Box<dyn Write>),
+    StringWriter(String),
+    Closed,
+}
+
+pub enum SeqIter {
+    Items(im_rc::vector::ConsumingIter<Rc<MalVal>>),
+    Lazy(Rc<LazySeq>),
+    Done,
+}
+
 pub enum MalFn {
     MalFunc(MalFunc),
-    RegularFn(Rc<dyn Fn(&[Rc<MalVal>]) -> MalResult>),
+    RegularFn(RegularFunc),
 }
 
-#[derive(PartialEq, Eq, Hash, Clone)]
-pub enum Hashable {
-    Keyword(String),
-    String(String),
-}
+#[derive(Clone)]
+pub struct Hashable(Rc<MalVal>);
 
 pub enum MalVal {
     Fn(Rc<MalFn>, Option<Rc<MalVal>>),
-    List(Vec<Rc<MalVal>>, Option<Rc<MalVal>>),
-    Vector(Vec<Rc<MalVal>>, Option<Rc<MalVal>>),
+    List(Vector<Rc<MalVal>>, Option<Rc<MalVal>>),
+    Vector(Vector<Rc<MalVal>>, Option<Rc<MalVal>>),
     HashMap(HashMap<Hashable, Rc<MalVal>>, Option<Rc<MalVal>>),
+    Set(HashSet<Hashable>, Option<Rc<MalVal>>),
+    SortedMap(SortedMap, Option<Rc<MalVal>>),
+    LazySeq(Rc<LazySeq>, Option<Rc<MalVal>>),
     Keyword(String),
     String(String),
+    Char(char),
     Integer(i64),
+    BigInt(BigInt),
+    Ratio(BigRational),
+    Float(f64),
     Bool(bool),
     Nil,
     Symbol(String),
+    Regex(MalRegex),
     Atom(Cell<Rc<MalVal>>),
+    Port(Rc<Port>),
+}
+
+impl MalError {
+    pub fn raise(kind: &str, message: String) -> Self {
+        let data = HashMap::from(vec![(
+            Hashable::keyword("type"),
+            Rc::new(MalVal::Keyword(kind.to_string())),
+        )]);
+        MalError::throw(Rc::new(MalVal::ex_info(
+            message,
+            Rc::new(MalVal::HashMap(data, None)),
+            None,
+        )))
+    }
+
+    pub fn io(path: &str, error: std::io::Error) -> Self {
+        let data = HashMap::from(vec![
+            (
+                Hashable::keyword("type"),
+                Rc::new(MalVal::Keyword("io-error".to_string())),
+            ),
+            (
+                Hashable::keyword("path"),
+                Rc::new(MalVal::String(path.to_string())),
+            ),
+            (
+                Hashable::keyword("os-error"),
+                Rc::new(MalVal::String(error.to_string())),
+            ),
+        ]);
+        MalError::throw(Rc::new(MalVal::ex_info(
+            format!("{path}: {error}"),
+            Rc::new(MalVal::HashMap(data, None)),
+            None,
+        )))
+    }
+
+    pub fn with_frame(self, name: &str, form: Option<&MalVal>) -> Self {
+        let MalError::Throw(exception, mut trace) = self else {
+            return self;
+        };
+        let mut frame = HashMap::from(vec![(
+            Hashable::keyword("fn"),
+            Rc::new(MalVal::String(name.to_string())),
+        )]);
+        if let Some(MalVal::List(_, Some(position))) = form {
+            for key in ["file", "line", "column"] {
+                if let Some(v) = position.ex_field(key) {
+                    frame.insert(Hashable::keyword(key), v);
+                }
+            }
+        }
+        trace.push_back(Rc::new(MalVal::HashMap(frame, None)));
+        MalError::Throw(exception, trace)
+    }
+
+    pub fn throw(exception: Rc<MalVal>) -> Self {
+        let trace = match exception.stacktrace().as_deref() {
+            Some(MalVal::Vector(trace, _)) => trace.clone(),
+            _ => Vector::new(),
+        };
+        MalError::Throw(exception, trace)
+    }
+
+    pub fn into_parts(self) -> (Rc<MalVal>, Trace) {
+        match self {
+            MalError::Throw(exception, trace) => (exception, trace),
+            e => (e.into(), Vector::new()),
+        }
+    }
+}
+
+impl Arity {
+    pub const fn exact(n: usize) -> Self {
+        Self {
+            min: n,
+            max: Some(n),
+        }
+    }
+
+    pub const fn at_least(n: usize) -> Self {
+        Self { min: n, max: None }
+    }
+
+    pub const fn between(min: usize, max: usize) -> Self {
+        Self {
+            min,
+            max: Some(max),
+        }
+    }
+
+    pub fn of_params(params: &[String]) -> Self {
+        match params.iter().position(|p| p == "&") {
+            Some(i) => Self::at_least(i),
+            None => Self::exact(params.len()),
+        }
+    }
+
+    pub fn check(&self, name: &str, n: usize) -> Result<(), MalError> {
+        if n < self.min || self.max.is_some_and(|max| n > max) {
+            Err(Self::error(name, n))
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn error(name: &str, n: usize) -> MalError {
+        MalError::raise(
+            "arity-error",
+            format!("wrong number of args ({n}) passed to {name}"),
+        )
+    }
 }
 
 impl MalFunc {
     pub fn construct_marco(&self) -> Self {
         MalFunc {
-            ast: self.ast.clone(),
-            params: self.params.to_vec(),
-            env: self.env.clone(),
-            func: self.func,
             is_marco: true,
+            ..self.clone()
+        }
+    }
+
+    pub fn with_name(&self, name: &str) -> Self {
+        MalFunc {
+            name: Some(name.to_string()),
+            ..self.clone()
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match &self.name {
+            Some(name) => name.clone(),
+            None => match self.env.borrow().fn_name() {
+                Some(outer) => format!("{outer}/fn"),
+                None => "fn".to_string(),
+            },
         }
     }
 
+    pub fn bind(&self, args: &[Rc<MalVal>]) -> Result<Env, MalError> {
+        let name = self.name();
+        self.arity.check(&name, args.len())?;
+        let mut n_env = Env::for_call(self.env.clone(), name.clone());
+        n_env.bind_expr(&name, self.params.clone(), args.to_vec())?;
+        Ok(n_env)
+    }
+
     pub fn run(&self, args: &[Rc<MalVal>]) -> MalResult {
-        let mut n_env = Env::new(self.env.clone());
-        n_env.bind_expr(self.params.clone(), args.to_vec());
+        let n_env = self.bind(args)?;
         (self.func)(self.ast.clone(), Rc::new(RefCell::new(n_env)))
+            .map_err(|e| e.with_frame(&self.name(), Some(&self.ast)))
+    }
+}
+
+impl RegularFunc {
+    pub fn run(&self, args: &[Rc<MalVal>]) -> MalResult {
+        self.arity.check(&self.name, args.len())?;
+        (self.func)(args)
     }
 }
 
@@ -85,7 +313,9 @@ impl MalFn {
         func: fn(Rc<MalVal>, Rc<RefCell<Env>>) -> MalResult,
     ) -> Self {
         Self::MalFunc(MalFunc {
+            name: None,
             ast,
+            arity: Arity::of_params(&params),
             params,
             env,
             func,
@@ -93,9 +323,21 @@ impl MalFn {
         })
     }
 
+    pub fn regular_fn(
+        name: &str,
+        arity: Arity,
+        func: impl Fn(&[Rc<MalVal>]) -> MalResult + 'static,
+    ) -> Self {
+        Self::RegularFn(RegularFunc {
+            name: name.to_string(),
+            arity,
+            func: Rc::new(func),
+        })
+    }
+
     pub fn run(&self, args: &[Rc<MalVal>]) -> MalResult {
         match self {
-            MalFn::RegularFn(func) => (func)(args),
+            MalFn::RegularFn(func) => func.run(args),
             MalFn::MalFunc(func) => func.run(args),
         }
     }
@@ -115,46 +357,570 @@ impl PartialEq for MalVal {
             (Self::Vector(l0, _), Self::List(r0, _)) => l0 == r0,
             (Self::Vector(l0, _), Self::Vector(r0, _)) => l0 == r0,
             (Self::HashMap(l0, _), Self::HashMap(r0, _)) => l0 == r0,
+            (Self::Set(l0, _), Self::Set(r0, _)) => l0 == r0,
+            (Self::LazySeq(..), Self::List(..) | Self::Vector(..) | Self::LazySeq(..))
+            | (Self::List(..) | Self::Vector(..), Self::LazySeq(..)) => {
+                let (Ok(mut l0), Ok(mut r0)) = (self.seq_iter(), other.seq_iter()) else {
+                    return false;
+                };
+                loop {
+                    match (l0.next(), r0.next()) {
+                        (None, None) => return true,
+                        (Some(Ok(l)), Some(Ok(r))) if l == r => {}
+                        _ => return false,
+                    }
+                }
+            }
+            (Self::SortedMap(l0, _), Self::SortedMap(r0, _)) => l0 == r0,
+            (Self::SortedMap(l0, _), Self::HashMap(r0, _))
+            | (Self::HashMap(r0, _), Self::SortedMap(l0, _)) => {
+                l0.len() == r0.len()
+                    && l0.iter().all(|(k, v)| {
+                        Hashable::try_from(k.clone()).ok().and_then(|k| r0.get(&k)) == Some(v)
+                    })
+            }
             (Self::Keyword(l0), Self::Keyword(r0)) => l0 == r0,
             (Self::String(l0), Self::String(r0)) => l0 == r0,
+            (Self::Char(l0), Self::Char(r0)) => l0 == r0,
             (Self::Integer(l0), Self::Integer(r0)) => l0 == r0,
+            (Self::Integer(l0), Self::BigInt(r0)) => &BigInt::from(*l0) == r0,
+            (Self::BigInt(l0), Self::Integer(r0)) => l0 == &BigInt::from(*r0),
+            (Self::BigInt(l0), Self::BigInt(r0)) => l0 == r0,
+            (Self::Ratio(l0), Self::Ratio(r0)) => l0 == r0,
+            (Self::Float(l0), Self::Float(r0)) => l0 == r0,
             (Self::Bool(l0), Self::Bool(r0)) => l0 == r0,
             (Self::Symbol(l0), Self::Symbol(r0)) => l0 == r0,
+            (Self::Regex(l0), Self::Regex(r0)) => l0.as_str() == r0.as_str(),
+            (Self::Port(l0), Self::Port(r0)) => Rc::ptr_eq(l0, r0),
             _ => core::mem::discriminant(self) == core::mem::discriminant(other),
         }
     }
 }
 
-impl Hashable {
-    pub fn pr_str(&self, readably: bool) -> String {
+impl Hash for MalVal {
+    fn hash<H: Hasher>(&self, state: &mut H) {
         match self {
-            Hashable::Keyword(keyword) => format!(":{keyword}"),
-            Hashable::String(string) => {
-                if readably {
-                    format!("{string:?}")
-                } else {
-                    string.to_string()
+            MalVal::List(list, _) | MalVal::Vector(list, _) => {
+                0u8.hash(state);
+                list.hash(state);
+            }
+            MalVal::LazySeq(seq, _) => {
+                0u8.hash(state);
+                for v in SeqIter::Lazy(seq.clone()).map_while(Result::ok) {
+                    v.hash(state);
+                }
+            }
+            MalVal::HashMap(map, _) => {
+                1u8.hash(state);
+                map.iter()
+                    .map(|(k, v)| entry_hash(&k.0, v))
+                    .fold(0u64, u64::wrapping_add)
+                    .hash(state);
+            }
+            MalVal::SortedMap(map, _) => {
+                1u8.hash(state);
+                map.iter()
+                    .map(|(k, v)| entry_hash(k, v))
+                    .fold(0u64, u64::wrapping_add)
+                    .hash(state);
+            }
+            MalVal::Set(set, _) => {
+                11u8.hash(state);
+                set.iter()
+                    .map(|k| {
+                        let mut hasher = DefaultHasher::new();
+                        k.hash(&mut hasher);
+                        hasher.finish()
+                    })
+                    .fold(0u64, u64::wrapping_add)
+                    .hash(state);
+            }
+            MalVal::Keyword(keyword) => {
+                2u8.hash(state);
+                keyword.hash(state);
+            }
+            MalVal::String(string) => {
+                3u8.hash(state);
+                string.hash(state);
+            }
+            MalVal::Char(c) => {
+                4u8.hash(state);
+                c.hash(state);
+            }
+            MalVal::Integer(int) => {
+                5u8.hash(state);
+                int.hash(state);
+            }
+            MalVal::BigInt(int) => match int.to_i64() {
+                Some(int) => MalVal::Integer(int).hash(state),
+                None => {
+                    6u8.hash(state);
+                    int.hash(state);
+                }
+            },
+            MalVal::Ratio(ratio) => {
+                7u8.hash(state);
+                ratio.hash(state);
+            }
+            MalVal::Float(float) => {
+                8u8.hash(state);
+                (float + 0.0).to_bits().hash(state);
+            }
+            MalVal::Bool(b) => {
+                9u8.hash(state);
+                b.hash(state);
+            }
+            MalVal::Symbol(symbol) => {
+                10u8.hash(state);
+                symbol.hash(state);
+            }
+            MalVal::Regex(regex) => {
+                12u8.hash(state);
+                regex.as_str().hash(state);
+            }
+            MalVal::Nil | MalVal::Fn(..) | MalVal::Atom(_) | MalVal::Port(_) => {
+                core::mem::discriminant(self).hash(state);
+            }
+        }
+    }
+}
+
+fn entry_hash(k: &MalVal, v: &MalVal) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    k.hash(&mut hasher);
+    v.hash(&mut hasher);
+    hasher.finish()
+}
+
+pub fn compare_with(
+    comparator: Option<&Rc<MalVal>>,
+    a: &Rc<MalVal>,
+    b: &Rc<MalVal>,
+) -> Result<Ordering, MalError> {
+    let Some(comparator) = comparator else {
+        return Ok(a.total_cmp(b));
+    };
+    let MalVal::Fn(f, _) = comparator.as_ref() else {
+        return Err(MalError::raise(
+            "type-error",
+            format!(
+                "comparator: expected function, got {}",
+                comparator.type_name()
+            ),
+        ));
+    };
+    match f.run(&[a.clone(), b.clone()])?.as_ref() {
+        MalVal::Bool(true) => Ok(Ordering::Less),
+        MalVal::Bool(false) | MalVal::Nil => match f.run(&[b.clone(), a.clone()])?.as_ref() {
+            MalVal::Bool(false) | MalVal::Nil => Ok(Ordering::Equal),
+            _ => Ok(Ordering::Greater),
+        },
+        MalVal::Integer(i) => Ok(i.cmp(&0)),
+        MalVal::Float(f) => Ok(f.partial_cmp(&0.0).unwrap_or(Ordering::Equal)),
+        v => Err(MalError::raise(
+            "type-error",
+            format!(
+                "comparator: expected number or boolean, got {}",
+                v.type_name()
+            ),
+        )),
+    }
+}
+
+pub fn sorted_entries(map: &HashMap<Hashable, Rc<MalVal>>) -> Vec<(&Hashable, &Rc<MalVal>)> {
+    let mut entries = map.iter().collect::<Vec<_>>();
+    entries.sort_by(|(a, _), (b, _)| a.0.total_cmp(&b.0));
+    entries
+}
+
+pub fn compile_regex(pattern: &str) -> Result<MalRegex, String> {
+    Ok(MalRegex {
+        regex: compile(pattern)?,
+        anchored: OnceCell::new(),
+    })
+}
+
+fn compile(pattern: &str) -> Result<Regex, String> {
+    Regex::new(pattern).map_err(|e| match e {
+        regex::Error::Syntax(message) => message
+            .lines()
+            .last()
+            .unwrap_or_default()
+            .trim_start_matches("error: ")
+            .to_string(),
+        e => e.to_string(),
+    })
+}
+
+impl PartialEq for SortedMap {
+    fn eq(&self, other: &Self) -> bool {
+        if self.len() != other.len() {
+            return false;
+        }
+        match (&self.comparator, &other.comparator) {
+            (None, None) => self.entries == other.entries,
+            (Some(l0), Some(r0)) if Rc::ptr_eq(l0, r0) => self.entries == other.entries,
+            _ => self
+                .iter()
+                .all(|(k, v)| other.get(k).ok().flatten().as_ref() == Some(v)),
+        }
+    }
+}
+
+impl SortedMap {
+    pub fn new(comparator: Option<Rc<MalVal>>) -> Self {
+        SortedMap {
+            entries: Vector::new(),
+            comparator,
+        }
+    }
+
+    fn compare(&self, a: &Rc<MalVal>, b: &Rc<MalVal>) -> Result<Ordering, MalError> {
+        compare_with(self.comparator.as_ref(), a, b)
+    }
+
+    fn search(&self, k: &Rc<MalVal>) -> Result<Result<usize, usize>, MalError> {
+        let (mut low, mut high) = (0, self.entries.len());
+        while low < high {
+            let mid = (low + high) / 2;
+            match self.compare(&self.entries[mid].0, k)? {
+                Ordering::Less => low = mid + 1,
+                Ordering::Greater => high = mid,
+                Ordering::Equal => return Ok(Ok(mid)),
+            }
+        }
+        Ok(Err(low))
+    }
+
+    pub fn get(&self, k: &Rc<MalVal>) -> Result<Option<Rc<MalVal>>, MalError> {
+        Ok(self.search(k)?.ok().map(|i| self.entries[i].1.clone()))
+    }
+
+    pub fn insert(&mut self, k: Rc<MalVal>, v: Rc<MalVal>) -> Result<(), MalError> {
+        match self.search(&k)? {
+            Ok(i) => self.entries[i].1 = v,
+            Err(i) => self.entries.insert(i, (k, v)),
+        }
+        Ok(())
+    }
+
+    pub fn remove(&mut self, k: &Rc<MalVal>) -> Result<(), MalError> {
+        if let Ok(i) = self.search(k)? {
+            self.entries.remove(i);
+        }
+        Ok(())
+    }
+
+    pub fn iter(&self) -> im_rc::vector::Iter<'_, (Rc<MalVal>, Rc<MalVal>)> {
+        self.entries.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl MalRegex {
+    pub fn anchored(&self) -> Result<&Regex, String> {
+        if let Some(anchored) = self.anchored.get() {
+            return Ok(anchored);
+        }
+        let anchored = compile(&format!("\\A(?:{})\\z", self.regex.as_str()))?;
+        Ok(self.anchored.get_or_init(|| anchored))
+    }
+}
+
+impl Deref for MalRegex {
+    type Target = Regex;
+
+    fn deref(&self) -> &Regex {
+        &self.regex
+    }
+}
+
+impl LazySeq {
+    pub fn new(thunk: impl FnOnce() -> MalResult + 'static) -> Self {
+        LazySeq(RefCell::new(LazyState::Pending(Box::new(thunk))))
+    }
+
+    pub fn cons(first: Rc<MalVal>, rest: Rc<MalVal>) -> Self {
+        LazySeq(RefCell::new(LazyState::Realized(Some((first, rest)))))
+    }
+
+    pub fn is_realized(&self) -> bool {
+        matches!(
+            *self.0.borrow(),
+            LazyState::Realized(_) | LazyState::Failed(..)
+        )
+    }
+
+    pub fn step(&self) -> Result<SeqStep, MalError> {
+        let result = match self.0.replace(LazyState::Realizing) {
+            LazyState::Pending(thunk) => thunk().and_then(|v| v.uncons()),
+            LazyState::Realizing => {
+                return Err(MalError::raise(
+                    "illegal-state",
+                    "lazy-seq: realized recursively".to_string(),
+                ))
+            }
+            LazyState::Realized(cell) => Ok(cell),
+            LazyState::Failed(e, trace) => Err(MalError::Throw(e, trace)),
+        };
+        match result {
+            Ok(cell) => {
+                self.0.replace(LazyState::Realized(cell.clone()));
+                Ok(cell)
+            }
+            Err(e) => {
+                let (e, trace) = e.into_parts();
+                self.0.replace(LazyState::Failed(e.clone(), trace.clone()));
+                Err(MalError::Throw(e, trace))
+            }
+        }
+    }
+}
+
+impl Drop for LazySeq {
+    fn drop(&mut self) {
+        let LazyState::Realized(Some((_, mut rest))) = self.0.replace(LazyState::Realizing) else {
+            return;
+        };
+        while let Ok(MalVal::LazySeq(seq, _)) = Rc::try_unwrap(rest) {
+            let Ok(seq) = Rc::try_unwrap(seq) else {
+                return;
+            };
+            let LazyState::Realized(Some((_, next))) = seq.0.replace(LazyState::Realizing) else {
+                return;
+            };
+            rest = next;
+        }
+    }
+}
+
+impl Port {
+    pub fn stdin() -> Self {
+        Self::with_state("stdin", PortState::Stdin)
+    }
+
+    pub fn stdout() -> Self {
+        Self::with_state("stdout", PortState::Stdout)
+    }
+
+    pub fn stderr() -> Self {
+        Self::with_state("stderr", PortState::Stderr)
+    }
+
+    pub fn reader(name: &str, reader: impl BufRead + 'static) -> Self {
+        Self::with_state(name, PortState::Reader(Box::new(reader)))
+    }
+
+    pub fn writer(name: &str, writer: impl Write + 'static) -> Self {
+        Self::with_state(name, PortState::Writer(Box::new(writer)))
+    }
+
+    pub fn string_writer() -> Self {
+        Self::with_state("string", PortState::StringWriter(String::new()))
+    }
+
+    fn with_state(name: &str, state: PortState) -> Self {
+        Self {
+            name: name.to_string(),
+            state: RefCell::new(state),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn error(&self, message: &str) -> MalError {
+        MalError::io(&self.name, std::io::Error::other(message))
+    }
+
+    pub fn read_line(&self) -> Result<Option<String>, MalError> {
+        let mut line = String::new();
+        let read = match &mut *self.state.borrow_mut() {
+            PortState::Stdin => std::io::stdin().read_line(&mut line),
+            PortState::Reader(reader) => reader.read_line(&mut line),
+            PortState::Closed => return Err(self.error("port is closed")),
+            _ => return Err(self.error("port is not readable")),
+        };
+        match read.map_err(|e| MalError::io(&self.name, e))? {
+            0 => Ok(None),
+            _ => {
+                if line.ends_with('\n') {
+                    line.pop();
+                    if line.ends_with('\r') {
+                        line.pop();
+                    }
                 }
+                Ok(Some(line))
             }
         }
     }
+
+    pub fn write_str(&self, s: &str) -> Result<(), MalError> {
+        let written = match &mut *self.state.borrow_mut() {
+            PortState::Stdout => std::io::stdout().write_all(s.as_bytes()),
+            PortState::Stderr => std::io::stderr().write_all(s.as_bytes()),
+            PortState::Writer(writer) => writer.write_all(s.as_bytes()),
+            PortState::StringWriter(buffer) => {
+                buffer.push_str(s);
+                Ok(())
+            }
+            PortState::Closed => return Err(self.error("port is closed")),
+            _ => return Err(self.error("port is not writable")),
+        };
+        written.map_err(|e| MalError::io(&self.name, e))
+    }
+
+    pub fn flush(&self) -> Result<(), MalError> {
+        let flushed = match &mut *self.state.borrow_mut() {
+            PortState::Stdout => std::io::stdout().flush(),
+            PortState::Stderr => std::io::stderr().flush(),
+            PortState::Writer(writer) => writer.flush(),
+            PortState::Closed => return Err(self.error("port is closed")),
+            _ => Ok(()),
+        };
+        flushed.map_err(|e| MalError::io(&self.name, e))
+    }
+
+    pub fn close(&self) -> Result<(), MalError> {
+        match self.state.replace(PortState::Closed) {
+            PortState::Writer(mut writer) => {
+                writer.flush().map_err(|e| MalError::io(&self.name, e))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn contents(&self) -> Option<String> {
+        match &*self.state.borrow() {
+            PortState::StringWriter(buffer) => Some(buffer.clone()),
+            _ => None,
+        }
+    }
 }
 
-impl From<&MalVal> for Hashable {
-    fn from(v: &MalVal) -> Self {
-        match v {
-            MalVal::Keyword(s) => Hashable::Keyword(s.to_string()),
-            MalVal::String(s) => Hashable::String(s.to_string()),
-            _ => panic!("cannot hash"),
+impl Iterator for SeqIter {
+    type Item = MalResult;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            SeqIter::Items(iter) => iter.next().map(Ok),
+            SeqIter::Lazy(seq) => {
+                let step = seq.step().and_then(|cell| match cell {
+                    Some((first, rest)) => Ok(Some((first, rest.seq_iter()?))),
+                    None => Ok(None),
+                });
+                match step {
+                    Ok(Some((first, rest))) => {
+                        *self = rest;
+                        Some(Ok(first))
+                    }
+                    Ok(None) => {
+                        *self = SeqIter::Done;
+                        None
+                    }
+                    Err(e) => {
+                        *self = SeqIter::Done;
+                        Some(Err(e))
+                    }
+                }
+            }
+            SeqIter::Done => None,
         }
     }
 }
 
-impl From<&Hashable> for MalVal {
-    fn from(v: &Hashable) -> Self {
-        match v {
-            Hashable::Keyword(s) => MalVal::Keyword(s.to_string()),
-            Hashable::String(s) => MalVal::String(s.to_string()),
+impl Hashable {
+    pub fn keyword(name: &str) -> Self {
+        Hashable(Rc::new(MalVal::Keyword(name.to_string())))
+    }
+
+    pub fn string(s: &str) -> Self {
+        Hashable(Rc::new(MalVal::String(s.to_string())))
+    }
+
+    pub fn pr_str(&self, readably: bool) -> String {
+        self.0.pr_str(readably)
+    }
+}
+
+impl PartialEq for Hashable {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl Eq for Hashable {}
+
+impl Hash for Hashable {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state);
+    }
+}
+
+impl TryFrom<Rc<MalVal>> for Hashable {
+    type Error = MalError;
+
+    fn try_from(v: Rc<MalVal>) -> Result<Self, Self::Error> {
+        v.realize()?;
+        match v.unhashable() {
+            Some(type_name) => Err(MalError::raise(
+                "type-error",
+                format!("{type_name} cannot be used as a map key"),
+            )),
+            None => Ok(Hashable(v)),
+        }
+    }
+}
+
+impl From<&Hashable> for Rc<MalVal> {
+    fn from(k: &Hashable) -> Self {
+        k.0.clone()
+    }
+}
+
+pub(crate) const CHAR_NAMES: [(&str, char); 6] = [
+    ("newline", '\n'),
+    ("space", ' '),
+    ("tab", '\t'),
+    ("return", '\r'),
+    ("backspace", '\u{8}'),
+    ("formfeed", '\u{c}'),
+];
+
+fn escape(s: &str) -> String {
+    let mut buffer = String::with_capacity(s.len() + 2);
+    buffer.push('"');
+    for c in s.chars() {
+        match c {
+            '\\' => buffer.push_str("\\\\"),
+            '"' => buffer.push_str("\\\""),
+            '\n' => buffer.push_str("\\n"),
+            '\t' => buffer.push_str("\\t"),
+            '\r' => buffer.push_str("\\r"),
+            '\0' => buffer.push_str("\\0"),
+            c if c.is_control() => buffer.push_str(&format!("\\u{:04x}", c as u32)),
+            c => buffer.push(c),
+        }
+    }
+    buffer.push('"');
+    buffer
+}
+
+impl From<BigRational> for MalVal {
+    fn from(r: BigRational) -> Self {
+        if !r.is_integer() {
+            MalVal::Ratio(r)
+        } else {
+            let i = r.to_integer();
+            i.to_i64().map_or(MalVal::BigInt(i), MalVal::Integer)
         }
     }
 }
@@ -162,14 +928,299 @@ impl From<&Hashable> for MalVal {
 impl From<MalError> for Rc<MalVal> {
     fn from(e: MalError) -> Self {
         match e {
-            MalError::Throw(v) => v,
+            MalError::Throw(v, trace) => v.with_stacktrace(trace),
             MalError::Continue => Rc::new(MalVal::Nil),
-            _ => Rc::new(MalVal::String(e.to_string())),
+            MalError::Read(e) => {
+                let mut data = HashMap::from(vec![
+                    (
+                        Hashable::keyword("type"),
+                        Rc::new(MalVal::Keyword("reader-error".to_string())),
+                    ),
+                    (
+                        Hashable::keyword("line"),
+                        Rc::new(MalVal::Integer(e.line as i64)),
+                    ),
+                    (
+                        Hashable::keyword("column"),
+                        Rc::new(MalVal::Integer(e.column as i64)),
+                    ),
+                ]);
+                if let Some(file) = e.file {
+                    data.insert(Hashable::keyword("file"), Rc::new(MalVal::String(file)));
+                }
+                Rc::new(MalVal::ex_info(
+                    e.message,
+                    Rc::new(MalVal::HashMap(data, None)),
+                    None,
+                ))
+            }
+            MalError::Other(s) => MalError::raise("error", s).into(),
         }
     }
 }
 
 impl MalVal {
+    pub fn ex_info(message: String, data: Rc<MalVal>, cause: Option<Rc<MalVal>>) -> MalVal {
+        let mut map = HashMap::from(vec![
+            (
+                Hashable::keyword("message"),
+                Rc::new(MalVal::String(message)),
+            ),
+            (Hashable::keyword("data"), data),
+        ]);
+        if let Some(cause) = cause {
+            map.insert(Hashable::keyword("cause"), cause);
+        }
+        MalVal::HashMap(map, None)
+    }
+
+    pub fn ex_field(&self, field: &str) -> Option<Rc<MalVal>> {
+        match self {
+            MalVal::HashMap(h, _) => h.get(&Hashable::keyword(field)).cloned(),
+            _ => None,
+        }
+    }
+
+    pub fn stacktrace(&self) -> Option<Rc<MalVal>> {
+        match self {
+            MalVal::HashMap(_, Some(meta)) => meta.ex_field("stacktrace"),
+            _ => None,
+        }
+    }
+
+    pub fn with_stacktrace(self: &Rc<Self>, trace: Trace) -> Rc<MalVal> {
+        match self.as_ref() {
+            MalVal::HashMap(map, meta) if !trace.is_empty() => {
+                let mut meta = match meta.as_deref() {
+                    Some(MalVal::HashMap(meta, _)) => meta.clone(),
+                    _ => HashMap::new(),
+                };
+                meta.insert(
+                    Hashable::keyword("stacktrace"),
+                    Rc::new(MalVal::Vector(trace, None)),
+                );
+                Rc::new(MalVal::HashMap(
+                    map.clone(),
+                    Some(Rc::new(MalVal::HashMap(meta, None))),
+                ))
+            }
+            _ => self.clone(),
+        }
+    }
+
+    pub fn with_name(self: &Rc<Self>, name: &str) -> Rc<MalVal> {
+        match self.as_ref() {
+            MalVal::Fn(f, meta) => match f.as_ref() {
+                MalFn::MalFunc(func) if func.name.is_none() => Rc::new(MalVal::Fn(
+                    Rc::new(MalFn::MalFunc(func.with_name(name))),
+                    meta.clone(),
+                )),
+                _ => self.clone(),
+            },
+            _ => self.clone(),
+        }
+    }
+
+    fn rank(&self) -> u8 {
+        match self {
+            MalVal::Nil => 0,
+            MalVal::Bool(_) => 1,
+            MalVal::Integer(_) | MalVal::BigInt(_) | MalVal::Ratio(_) | MalVal::Float(_) => 2,
+            MalVal::Char(_) => 3,
+            MalVal::String(_) => 4,
+            MalVal::Symbol(_) => 5,
+            MalVal::Keyword(_) => 6,
+            MalVal::List(..) | MalVal::Vector(..) | MalVal::LazySeq(..) => 7,
+            MalVal::HashMap(..) | MalVal::SortedMap(..) => 8,
+            MalVal::Set(..) => 9,
+            MalVal::Regex(_) => 10,
+            MalVal::Fn(..) => 11,
+            MalVal::Atom(_) => 12,
+            MalVal::Port(_) => 13,
+        }
+    }
+
+    fn to_ratio(&self) -> Option<BigRational> {
+        match self {
+            MalVal::Integer(i) => Some(BigRational::from_integer(BigInt::from(*i))),
+            MalVal::BigInt(i) => Some(BigRational::from_integer(i.clone())),
+            MalVal::Ratio(r) => Some(r.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn total_cmp(&self, other: &MalVal) -> Ordering {
+        match (self, other) {
+            (MalVal::Integer(a), MalVal::Integer(b)) => a.cmp(b),
+            (MalVal::Float(a), MalVal::Float(b)) => a.partial_cmp(b).unwrap_or(Ordering::Equal),
+            (MalVal::Float(a), b) if b.is_number() => {
+                let b = b.to_ratio().and_then(|b| b.to_f64()).unwrap_or(f64::NAN);
+                a.partial_cmp(&b)
+                    .unwrap_or(Ordering::Equal)
+                    .then(Ordering::Greater)
+            }
+            (a, MalVal::Float(_)) if a.is_number() => other.total_cmp(self).reverse(),
+            (a, b) if a.is_number() && b.is_number() => a.to_ratio().cmp(&b.to_ratio()),
+            (MalVal::Bool(a), MalVal::Bool(b)) => a.cmp(b),
+            (MalVal::Char(a), MalVal::Char(b)) => a.cmp(b),
+            (MalVal::String(a), MalVal::String(b))
+            | (MalVal::Symbol(a), MalVal::Symbol(b))
+            | (MalVal::Keyword(a), MalVal::Keyword(b)) => a.cmp(b),
+            (
+                MalVal::List(a, _) | MalVal::Vector(a, _),
+                MalVal::List(b, _) | MalVal::Vector(b, _),
+            ) => a
+                .iter()
+                .zip(b.iter())
+                .map(|(a, b)| a.total_cmp(b))
+                .find(|o| o.is_ne())
+                .unwrap_or_else(|| a.len().cmp(&b.len())),
+            (a, b) if a.rank() == b.rank() && a.rank() < 11 => a.pr_str(true).cmp(&b.pr_str(true)),
+            (a, b) => a.rank().cmp(&b.rank()),
+        }
+    }
+
+    fn unhashable(&self) -> Option<&'static str> {
+        match self {
+            MalVal::Fn(..) | MalVal::Atom(_) | MalVal::Port(_) => Some(self.type_name()),
+            MalVal::Float(f) if f.is_nan() => Some(self.type_name()),
+            MalVal::LazySeq(..) => self.seq_iter().ok()?.find_map(|v| v.ok()?.unhashable()),
+            MalVal::List(list, _) | MalVal::Vector(list, _) => {
+                list.iter().find_map(|v| v.unhashable())
+            }
+            MalVal::HashMap(map, _) => map.values().find_map(|v| v.unhashable()),
+            MalVal::SortedMap(map, _) => map
+                .iter()
+                .find_map(|(k, v)| k.unhashable().or_else(|| v.unhashable())),
+            MalVal::Set(set, _) => set.iter().find_map(|k| k.0.unhashable()),
+            _ => None,
+        }
+    }
+
+    pub fn seq_iter(&self) -> Result<SeqIter, MalError> {
+        match self {
+            MalVal::List(list, _) | MalVal::Vector(list, _) => {
+                Ok(SeqIter::Items(list.clone().into_iter()))
+            }
+            MalVal::LazySeq(seq, _) => Ok(SeqIter::Lazy(seq.clone())),
+            MalVal::Set(set, _) => Ok(SeqIter::Items(
+                set.iter()
+                    .map(|k| k.into())
+                    .collect::<Vector<_>>()
+                    .into_iter(),
+            )),
+            MalVal::String(string) => Ok(SeqIter::Items(
+                string
+                    .chars()
+                    .map(|c| Rc::new(MalVal::Char(c)))
+                    .collect::<Vector<_>>()
+                    .into_iter(),
+            )),
+            MalVal::Nil => Ok(SeqIter::Done),
+            v => Err(MalError::raise(
+                "type-error",
+                format!("{} is not a sequence", v.type_name()),
+            )),
+        }
+    }
+
+    pub fn equals(&self, other: &MalVal) -> Result<bool, MalError> {
+        match (self, other) {
+            (MalVal::LazySeq(..), MalVal::List(..) | MalVal::Vector(..) | MalVal::LazySeq(..))
+            | (MalVal::List(..) | MalVal::Vector(..), MalVal::LazySeq(..)) => {
+                let (mut l0, mut r0) = (self.seq_iter()?, other.seq_iter()?);
+                loop {
+                    match (l0.next().transpose()?, r0.next().transpose()?) {
+                        (None, None) => return Ok(true),
+                        (Some(l), Some(r)) if l.equals(&r)? => {}
+                        _ => return Ok(false),
+                    }
+                }
+            }
+            (
+                MalVal::List(l0, _) | MalVal::Vector(l0, _),
+                MalVal::List(r0, _) | MalVal::Vector(r0, _),
+            ) => {
+                if l0.len() != r0.len() {
+                    return Ok(false);
+                }
+                for (l, r) in l0.iter().zip(r0) {
+                    if !l.equals(r)? {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+            _ => Ok(self == other),
+        }
+    }
+
+    pub fn realize(&self) -> Result<(), MalError> {
+        match self {
+            MalVal::LazySeq(..) => self.seq_iter()?.try_for_each(|v| v?.realize()),
+            MalVal::List(list, _) | MalVal::Vector(list, _) => {
+                list.iter().try_for_each(|v| v.realize())
+            }
+            MalVal::HashMap(map, _) => map.values().try_for_each(|v| v.realize()),
+            MalVal::SortedMap(map, _) => map.iter().try_for_each(|(_, v)| v.realize()),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn uncons(&self) -> Result<SeqStep, MalError> {
+        match self {
+            MalVal::List(list, _) | MalVal::Vector(list, _) => Ok(list
+                .front()
+                .map(|first| (first.clone(), Rc::new(MalVal::List(list.skip(1), None))))),
+            MalVal::LazySeq(seq, _) => seq.step(),
+            v => {
+                let mut iter = v.seq_iter()?;
+                match iter.next().transpose()? {
+                    Some(first) => Ok(Some((
+                        first,
+                        Rc::new(MalVal::List(iter.collect::<Result<_, _>>()?, None)),
+                    ))),
+                    None => Ok(None),
+                }
+            }
+        }
+    }
+
+    pub fn is_number(&self) -> bool {
+        matches!(
+            self,
+            MalVal::Integer(_) | MalVal::BigInt(_) | MalVal::Ratio(_) | MalVal::Float(_)
+        )
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            MalVal::Fn(f, _) => match f.as_ref() {
+                MalFn::MalFunc(f) if f.is_marco => "macro",
+                _ => "function",
+            },
+            MalVal::List(..) => "list",
+            MalVal::Vector(..) => "vector",
+            MalVal::HashMap(..) => "hash-map",
+            MalVal::SortedMap(..) => "sorted-map",
+            MalVal::LazySeq(..) => "lazy-seq",
+            MalVal::Set(..) => "set",
+            MalVal::Keyword(_) => "keyword",
+            MalVal::String(_) => "string",
+            MalVal::Char(_) => "char",
+            MalVal::Integer(_) => "integer",
+            MalVal::BigInt(_) => "bigint",
+            MalVal::Ratio(_) => "ratio",
+            MalVal::Float(_) => "float",
+            MalVal::Bool(_) => "boolean",
+            MalVal::Nil => "nil",
+            MalVal::Symbol(_) => "symbol",
+            MalVal::Regex(_) => "regex",
+            MalVal::Atom(_) => "atom",
+            MalVal::Port(_) => "port",
+        }
+    }
+
     pub fn pr_str(&self, readably: bool) -> String {
         match self {
             MalVal::Fn(..) => "#<function>".to_string(),
@@ -182,6 +1233,16 @@ impl MalVal {
                         .join(" ")
                 )
             }
+            MalVal::LazySeq(seq, _) => {
+                format!(
+                    "({})",
+                    SeqIter::Lazy(seq.clone())
+                        .map_while(Result::ok)
+                        .map(|v| v.pr_str(readably))
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                )
+            }
             MalVal::Vector(vector, _) => {
                 format!(
                     "[{}]",
@@ -195,7 +1256,8 @@ impl MalVal {
             MalVal::HashMap(map, _) => {
                 format!(
                     "{{{}}}",
-                    map.iter()
+                    sorted_entries(map)
+                        .iter()
                         .map(|(k, v)| format!(
                             "{} {}",
                             k.pr_str(readably),
@@ -205,16 +1267,79 @@ impl MalVal {
                         .join(" ")
                 )
             }
+            MalVal::SortedMap(map, _) => {
+                format!(
+                    "{{{}}}",
+                    map.iter()
+                        .map(|(k, v)| format!("{} {}", k.pr_str(readably), v.pr_str(readably)))
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                )
+            }
+            MalVal::Set(set, _) => {
+                let mut keys = set.iter().collect::<Vec<_>>();
+                keys.sort_by(|a, b| a.0.total_cmp(&b.0));
+                format!(
+                    "#{{{}}}",
+                    keys.iter()
+                        .map(|k| k.pr_str(readably))
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                )
+            }
             MalVal::Keyword(keyword) => format!(":{keyword}"),
             MalVal::String(string) => {
                 if readably {
-                    format!("{string:?}")
+                    escape(string)
                 } else {
                     string.to_string()
                 }
             }
+            MalVal::Char(c) => {
+                if readably {
+                    match CHAR_NAMES.iter().find(|(_, named)| named == c) {
+                        Some((name, _)) => format!("\\{name}"),
+                        None if c.is_control() => format!("\\u{:04x}", *c as u32),
+                        None => format!("\\{c}"),
+                    }
+                } else {
+                    c.to_string()
+                }
+            }
             MalVal::Integer(int) => format!("{int}"),
+            MalVal::BigInt(int) if readably => format!("{int}N"),
+            MalVal::BigInt(int) => format!("{int}"),
+            MalVal::Ratio(ratio) => format!("{ratio}"),
+            MalVal::Float(float) => {
+                if float.is_nan() {
+                    "##NaN".to_string()
+                } else if float.is_infinite() {
+                    if *float > 0.0 { "##Inf" } else { "##-Inf" }.to_string()
+                } else {
+                    format!("{float:?}")
+                }
+            }
             MalVal::Symbol(symbol) => symbol.to_string(),
+            MalVal::Regex(regex) => {
+                if readably {
+                    let mut buffer = String::from("#\"");
+                    let mut chars = regex.as_str().chars();
+                    while let Some(c) = chars.next() {
+                        match c {
+                            '\\' => {
+                                buffer.push(c);
+                                buffer.extend(chars.next());
+                            }
+                            '"' => buffer.push_str("\\\""),
+                            _ => buffer.push(c),
+                        }
+                    }
+                    buffer.push('"');
+                    buffer
+                } else {
+                    regex.as_str().to_string()
+                }
+            }
             MalVal::Bool(b) => format!("{b}"),
             MalVal::Nil => "nil".to_string(),
             MalVal::Atom(v) => {
@@ -222,6 +1347,7 @@ impl MalVal {
                 v.set(m.clone());
                 format!("(atom {})", m.as_ref().pr_str(readably))
             }
+            MalVal::Port(port) => format!("#<port {}>", port.name()),
         }
     }
 }
@@ -233,7 +1359,11 @@ impl Debug for MalVal {
             Self::Vector(arg0, _) => f.debug_tuple("Vector").field(arg0).finish(),
             Self::Keyword(arg0) => f.debug_tuple("Keyword").field(arg0).finish(),
             Self::String(arg0) => f.debug_tuple("String").field(arg0).finish(),
+            Self::Char(arg0) => f.debug_tuple("Char").field(arg0).finish(),
             Self::Integer(arg0) => f.debug_tuple("Integer").field(arg0).finish(),
+            Self::BigInt(arg0) => f.debug_tuple("BigInt").field(arg0).finish(),
+            Self::Ratio(arg0) => f.debug_tuple("Ratio").field(arg0).finish(),
+            Self::Float(arg0) => f.debug_tuple("Float").field(arg0).finish(),
             Self::Bool(arg0) => f.debug_tuple("Bool").field(arg0).finish(),
             Self::Nil => write!(f, "Nil"),
             Self::Symbol(arg0) => f.debug_tuple("Symbol").field(arg0).finish(),